@@ -0,0 +1,57 @@
+use flipside_sdk::result_set::ResultSet;
+use flipside_sdk::rpc::ColumnType;
+use serde_json::Value;
+use std::time::{Duration, Instant};
+
+const ROWS: usize = 100_000;
+const ITERATIONS: usize = 10;
+
+fn best_of(mut f: impl FnMut() -> ResultSet) -> (Duration, ResultSet) {
+    let mut best = Duration::MAX;
+    let mut result_set = ResultSet::default();
+    for _ in 0..ITERATIONS {
+        let start = Instant::now();
+        result_set = f();
+        best = best.min(start.elapsed());
+    }
+    (best, result_set)
+}
+
+fn main() {
+    let column_names = vec![
+        "block_number".to_string(),
+        "tx_hash".to_string(),
+        "amount".to_string(),
+        "success".to_string(),
+    ];
+    let column_types = vec![
+        ColumnType::Number,
+        ColumnType::String,
+        ColumnType::Number,
+        ColumnType::Boolean,
+    ];
+
+    let mut json = String::from("[");
+    let mut csv = String::new();
+    for i in 0..ROWS {
+        if i > 0 {
+            json.push(',');
+        }
+        json.push_str(&format!(r#"[{i},"0x{i:064x}",{}.5,true]"#, i * 3));
+        csv.push_str(&format!("{i},0x{i:064x},{}.5,true\n", i * 3));
+    }
+    json.push(']');
+
+    let (json_elapsed, from_json) = best_of(|| {
+        let rows: Vec<Value> = serde_json::from_str(&json).unwrap();
+        ResultSet::new(column_names.clone(), column_types.clone(), rows)
+    });
+    let (csv_elapsed, from_csv) =
+        best_of(|| ResultSet::from_csv(column_names.clone(), column_types.clone(), csv.as_bytes()));
+
+    assert_eq!(from_json.rows, from_csv.rows);
+
+    println!("{ROWS} rows, best of {ITERATIONS}");
+    println!("json: {json_elapsed:?}");
+    println!("csv:  {csv_elapsed:?}");
+}
@@ -0,0 +1,130 @@
+mod sql;
+
+use proc_macro::TokenStream;
+use quote::quote;
+use std::path::PathBuf;
+use syn::{parse_macro_input, Data, DeriveInput, Error, Fields, LitStr};
+
+/// Implements `flipside_sdk::row::FromQueryRow`, reading each field from the
+/// column of the same name.
+///
+/// Field attributes:
+/// - `#[query_row(rename = "column")]` reads from another column
+/// - `#[query_row(default)]` uses `Default::default()` for missing or null columns
+#[proc_macro_derive(FromQueryRow, attributes(query_row))]
+pub fn derive_from_query_row(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: DeriveInput) -> Result<proc_macro2::TokenStream, Error> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(Error::new_spanned(
+                    name,
+                    "FromQueryRow requires a struct with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(Error::new_spanned(
+                name,
+                "FromQueryRow can only be derived for structs",
+            ))
+        }
+    };
+
+    let mut inits = Vec::with_capacity(fields.len());
+    for field in fields {
+        let ident = field.ident.as_ref().unwrap();
+        let mut column = ident.to_string().trim_start_matches("r#").to_string();
+        let mut default = false;
+
+        for attr in field
+            .attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("query_row"))
+        {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("rename") {
+                    column = meta.value()?.parse::<LitStr>()?.value();
+                    Ok(())
+                } else if meta.path.is_ident("default") {
+                    default = true;
+                    Ok(())
+                } else {
+                    Err(meta.error("expected `rename` or `default`"))
+                }
+            })?;
+        }
+
+        inits.push(if default {
+            quote! { #ident: ::flipside_sdk::row::get_or_default(column_names, row, #column)? }
+        } else {
+            quote! { #ident: ::flipside_sdk::row::get(column_names, row, #column)? }
+        });
+    }
+
+    Ok(quote! {
+        impl #impl_generics ::flipside_sdk::row::FromQueryRow for #name #ty_generics #where_clause {
+            fn from_row(
+                column_names: &[::std::string::String],
+                row: &[::flipside_sdk::serde_json::Value],
+            ) -> ::std::result::Result<Self, ::flipside_sdk::row::RowError> {
+                ::std::result::Result::Ok(Self { #(#inits,)* })
+            }
+        }
+    })
+}
+
+/// Embeds a `.sql` file, resolved from the calling crate's `CARGO_MANIFEST_DIR`,
+/// as a `flipside_sdk::sql::SqlFile`. Unterminated literals or comments and
+/// unbalanced parentheses are compile errors, and the file's `:placeholders` are
+/// listed in `SqlFile::params`. TOML front-matter, as read by the query registry,
+/// is left out of the embedded SQL.
+#[proc_macro]
+pub fn include_sql(input: TokenStream) -> TokenStream {
+    let path = parse_macro_input!(input as LitStr);
+    expand_include_sql(path)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn expand_include_sql(path: LitStr) -> Result<proc_macro2::TokenStream, Error> {
+    let manifest_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap_or_default();
+    let full_path = PathBuf::from(manifest_dir).join(path.value());
+    let sql = std::fs::read_to_string(&full_path).map_err(|err| {
+        Error::new(
+            path.span(),
+            format!("failed to read {}: {}", full_path.display(), err),
+        )
+    })?;
+    let (front_matter, body) = flipside_sql::split_front_matter(&sql);
+    // Blank lines in place of the front-matter keep error line numbers right
+    let skipped_lines = sql[..sql.len() - body.len()].matches('\n').count();
+    let params = sql::check(&format!("{}{}", "\n".repeat(skipped_lines), body))
+        .map_err(|err| Error::new(path.span(), format!("{}: {}", path.value(), err)))?;
+    let full_path = full_path.to_string_lossy();
+
+    let sql = match front_matter {
+        // `include_str!` still makes the file a dependency of the build
+        Some(_) => quote! {{
+            const _: &str = ::std::include_str!(#full_path);
+            #body
+        }},
+        None => quote! { ::std::include_str!(#full_path) },
+    };
+    Ok(quote! {
+        ::flipside_sdk::sql::SqlFile {
+            sql: #sql,
+            params: &[#(#params),*],
+        }
+    })
+}
@@ -0,0 +1,79 @@
+use flipside_sql::{Lexer, TokenKind};
+
+/// Named `:placeholders` of `sql`, in order of first appearance, found by the lexer
+/// `flipside_sdk::sql` uses too: string literals, quoted identifiers, comments,
+/// casts and semi-structured paths are skipped. Fails on unterminated literals or
+/// comments, unbalanced parentheses and files without any statement.
+pub fn check(sql: &str) -> Result<Vec<String>, String> {
+    let mut params = Vec::new();
+    let mut depth = Vec::new();
+    let mut has_statement = false;
+
+    for token in Lexer::new(sql) {
+        let unterminated =
+            |what| format!("unterminated {} at line {}", what, line(sql, token.offset));
+        match token.kind {
+            TokenKind::StringLiteral { terminated: false } => {
+                return Err(unterminated("string literal"))
+            }
+            TokenKind::QuotedIdentifier { terminated: false } => {
+                return Err(unterminated("quoted identifier"))
+            }
+            TokenKind::BlockComment { terminated: false } => return Err(unterminated("comment")),
+            TokenKind::LineComment | TokenKind::BlockComment { .. } => continue,
+            TokenKind::OpenParen => depth.push(token.offset),
+            TokenKind::CloseParen => {
+                depth
+                    .pop()
+                    .ok_or_else(|| format!("unmatched `)` at line {}", line(sql, token.offset)))?;
+            }
+            TokenKind::Placeholder => {
+                let name = &token.text[1..];
+                if !params.iter().any(|param| param == name) {
+                    params.push(name.to_string());
+                }
+            }
+            _ => {}
+        }
+        has_statement |= !token.text.trim_start().is_empty() && token.text != ";";
+    }
+
+    if let Some(offset) = depth.pop() {
+        return Err(format!("unmatched `(` at line {}", line(sql, offset)));
+    }
+    if !has_statement {
+        return Err("the file contains no statement".to_string());
+    }
+    Ok(params)
+}
+
+fn line(sql: &str, offset: usize) -> usize {
+    sql[..offset].matches('\n').count() + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn params_are_listed_once_in_order() {
+        assert_eq!(
+            check("select :b, :a, ':c' from t where x = :b::int").unwrap(),
+            ["b", "a"]
+        );
+    }
+
+    #[test]
+    fn malformed_files_are_rejected() {
+        assert_eq!(
+            check("select 1\nwhere x = 'a").unwrap_err(),
+            "unterminated string literal at line 2"
+        );
+        assert_eq!(check("select (1").unwrap_err(), "unmatched `(` at line 1");
+        assert_eq!(check("select 1)").unwrap_err(), "unmatched `)` at line 1");
+        assert_eq!(
+            check("-- nothing\n;").unwrap_err(),
+            "the file contains no statement"
+        );
+    }
+}
@@ -0,0 +1,219 @@
+//! Lexer shared by `flipside_sdk` and `flipside_derive`, so placeholders are found
+//! the same way in `include_sql!` files at compile time and in queries at runtime.
+
+/// Piece of SQL found by `Lexer`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Token<'a> {
+    pub kind: TokenKind,
+    pub text: &'a str,
+    /// Byte offset of `text` in the lexed SQL
+    pub offset: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    StringLiteral {
+        terminated: bool,
+    },
+    QuotedIdentifier {
+        terminated: bool,
+    },
+    LineComment,
+    BlockComment {
+        terminated: bool,
+    },
+    /// `:name`, the name being `text` without its colon
+    Placeholder,
+    OpenParen,
+    CloseParen,
+    /// Any other character, including colons that don't start a placeholder
+    Other,
+}
+
+/// Splits SQL into string literals, quoted identifiers, comments, named
+/// `:placeholders` and single characters. A colon doesn't start a placeholder when
+/// it follows an identifier, so `::` casts and `col:path` accesses of
+/// semi-structured columns are left alone, nor when the name is empty or starts
+/// with a digit.
+#[derive(Debug, Clone)]
+pub struct Lexer<'a> {
+    sql: &'a str,
+    rest: &'a str,
+    prev: Option<char>,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(sql: &'a str) -> Self {
+        Self {
+            sql,
+            rest: sql,
+            prev: None,
+        }
+    }
+}
+
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        let rest = self.rest;
+        let c = rest.chars().next()?;
+
+        let (kind, len) = match c {
+            '\'' | '"' => {
+                let (len, terminated) = closing(rest, c);
+                let kind = if c == '\'' {
+                    TokenKind::StringLiteral { terminated }
+                } else {
+                    TokenKind::QuotedIdentifier { terminated }
+                };
+                (kind, len)
+            }
+            '-' if rest.starts_with("--") => (
+                TokenKind::LineComment,
+                rest.find('\n').unwrap_or(rest.len()),
+            ),
+            '/' if rest.starts_with("/*") => match rest[2..].find("*/") {
+                Some(end) => (TokenKind::BlockComment { terminated: true }, end + 4),
+                None => (TokenKind::BlockComment { terminated: false }, rest.len()),
+            },
+            '(' => (TokenKind::OpenParen, 1),
+            ')' => (TokenKind::CloseParen, 1),
+            ':' => {
+                let follows_identifier = self
+                    .prev
+                    .is_some_and(|c| c.is_alphanumeric() || "_:]\")".contains(c));
+                let name_len = rest[1..]
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(rest.len() - 1);
+                let name = &rest[1..1 + name_len];
+
+                if follows_identifier
+                    || name.is_empty()
+                    || name.starts_with(|c: char| c.is_ascii_digit())
+                {
+                    (TokenKind::Other, 1)
+                } else {
+                    (TokenKind::Placeholder, 1 + name_len)
+                }
+            }
+            c => (TokenKind::Other, c.len_utf8()),
+        };
+
+        let text = &rest[..len];
+        self.prev = text.chars().next_back();
+        self.rest = &rest[len..];
+        Some(Token {
+            kind,
+            text,
+            offset: self.sql.len() - rest.len(),
+        })
+    }
+}
+
+/// Splits a `.sql` file into its `---` delimited TOML front-matter, if any, and its
+/// SQL
+pub fn split_front_matter(contents: &str) -> (Option<&str>, &str) {
+    let front_matter = contents
+        .strip_prefix("---")
+        .and_then(|rest| {
+            rest.strip_prefix('\n')
+                .or_else(|| rest.strip_prefix("\r\n"))
+        })
+        .and_then(|rest| {
+            let mut offset = 0;
+            for line in rest.split_inclusive('\n') {
+                if line.trim_end() == "---" {
+                    return Some((&rest[..offset], &rest[offset + line.len()..]));
+                }
+                offset += line.len();
+            }
+            None
+        });
+
+    match front_matter {
+        Some((front_matter, sql)) => (Some(front_matter), sql),
+        None => (None, contents),
+    }
+}
+
+/// Length of the literal or quoted identifier at the start of `s`, up to its
+/// closing `quote`, and whether it was closed. Doubled quotes, and backslash
+/// escapes in literals, don't close it.
+fn closing(s: &str, quote: char) -> (usize, bool) {
+    let mut chars = s.char_indices().skip(1);
+    while let Some((i, c)) = chars.next() {
+        if c == '\\' && quote == '\'' {
+            chars.next();
+        } else if c == quote {
+            if s[i + 1..].starts_with(quote) {
+                chars.next();
+            } else {
+                return (i + 1, true);
+            }
+        }
+    }
+    (s.len(), false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn placeholders(sql: &str) -> Vec<&str> {
+        Lexer::new(sql)
+            .filter(|token| token.kind == TokenKind::Placeholder)
+            .map(|token| &token.text[1..])
+            .collect()
+    }
+
+    #[test]
+    fn placeholders_skip_literals_comments_casts_and_paths() {
+        let sql = "select ':a', \":b\", x::int, v:field, arr[0]:c, :d -- :e\n/* :f */ from t where y = :g";
+        assert_eq!(placeholders(sql), ["d", "g"]);
+        assert_eq!(placeholders("select :1, : x, :_ok"), ["_ok"]);
+    }
+
+    #[test]
+    fn escaped_quotes_do_not_close_literals() {
+        let tokens = Lexer::new(r"'it''s \' :a' :b").collect::<Vec<_>>();
+        assert_eq!(tokens[0].text, r"'it''s \' :a'");
+        assert_eq!(
+            tokens[0].kind,
+            TokenKind::StringLiteral { terminated: true }
+        );
+        assert_eq!(placeholders(r"'it''s \' :a' :b"), ["b"]);
+    }
+
+    #[test]
+    fn front_matter_is_split_from_the_sql() {
+        assert_eq!(
+            split_front_matter("---\nversion = \"2\"\n---\nselect :a\n"),
+            (Some("version = \"2\"\n"), "select :a\n")
+        );
+        assert_eq!(
+            split_front_matter("--- not front matter\nselect 1"),
+            (None, "--- not front matter\nselect 1")
+        );
+        assert_eq!(
+            split_front_matter("---\nunterminated"),
+            (None, "---\nunterminated")
+        );
+    }
+
+    #[test]
+    fn unterminated_tokens_run_to_the_end() {
+        let last = |sql| Lexer::new(sql).last().unwrap();
+        assert_eq!(
+            last("select 'abc").kind,
+            TokenKind::StringLiteral { terminated: false }
+        );
+        assert_eq!(
+            last("select \"abc").kind,
+            TokenKind::QuotedIdentifier { terminated: false }
+        );
+        let comment = last("select 1 /* :a");
+        assert_eq!(comment.kind, TokenKind::BlockComment { terminated: false });
+        assert_eq!(comment.offset, 9);
+    }
+}
@@ -0,0 +1,246 @@
+use crate::result_set::ResultSet;
+use crate::rpc::ColumnType;
+use serde_json::{Number, Value};
+use std::collections::HashMap;
+
+/// Rows of a result set grouped by the value of one column, in order of first
+/// appearance. See `ResultSet::group_by`.
+#[derive(Debug, Clone)]
+pub struct GroupBy<'a> {
+    result_set: &'a ResultSet,
+    key: usize,
+    groups: Vec<(Value, Vec<usize>)>,
+}
+
+impl<'a> GroupBy<'a> {
+    pub(crate) fn new(result_set: &'a ResultSet, key: usize) -> Self {
+        let mut index = HashMap::new();
+        let mut groups: Vec<(Value, Vec<usize>)> = Vec::new();
+
+        for (i, row) in result_set.rows.iter().enumerate() {
+            let value = row.get(key).cloned().unwrap_or(Value::Null);
+            let group = *index.entry(value.to_string()).or_insert_with(|| {
+                groups.push((value, Vec::new()));
+                groups.len() - 1
+            });
+            groups[group].1.push(i);
+        }
+
+        Self {
+            result_set,
+            key,
+            groups,
+        }
+    }
+
+    /// Group keys, in order of first appearance
+    pub fn keys(&self) -> impl Iterator<Item = &Value> {
+        self.groups.iter().map(|(key, _)| key)
+    }
+
+    pub fn count(&self) -> ResultSet {
+        self.aggregate("count", |rows| {
+            Some(Value::Number(Number::from(rows.len() as u64)))
+        })
+    }
+
+    /// Sums the numeric values of `column`, ignoring nulls and non-numbers.
+    /// Integers are summed exactly, so a sum too large for a JSON number is a
+    /// string of its digits.
+    pub fn sum(&self, column: &str) -> Option<ResultSet> {
+        self.numeric(column, "sum", |values| match values {
+            Numbers::Integers(values) => values
+                .iter()
+                .try_fold(0i128, |sum, &n| sum.checked_add(n))
+                .map(integer)
+                .or_else(|| float(values.iter().map(|&n| n as f64).sum())),
+            Numbers::Floats(values) => float(values.iter().sum()),
+        })
+    }
+
+    pub fn mean(&self, column: &str) -> Option<ResultSet> {
+        self.numeric(column, "mean", |values| {
+            let values = values.to_f64();
+            (!values.is_empty())
+                .then(|| values.iter().sum::<f64>() / values.len() as f64)
+                .and_then(float)
+        })
+    }
+
+    pub fn min(&self, column: &str) -> Option<ResultSet> {
+        self.numeric(column, "min", |values| match values {
+            Numbers::Integers(values) => values.iter().copied().min().map(integer),
+            Numbers::Floats(values) => values.iter().copied().reduce(f64::min).and_then(float),
+        })
+    }
+
+    pub fn max(&self, column: &str) -> Option<ResultSet> {
+        self.numeric(column, "max", |values| match values {
+            Numbers::Integers(values) => values.iter().copied().max().map(integer),
+            Numbers::Floats(values) => values.iter().copied().reduce(f64::max).and_then(float),
+        })
+    }
+
+    fn numeric(
+        &self,
+        column: &str,
+        name: &str,
+        f: impl Fn(&Numbers) -> Option<Value>,
+    ) -> Option<ResultSet> {
+        let index = self.result_set.column_index(column)?;
+
+        Some(self.aggregate(&format!("{}_{}", name, column), |rows| {
+            let values = rows
+                .iter()
+                .filter_map(|&i| self.result_set.rows[i].get(index))
+                .filter(|value| as_f64(value).is_some())
+                .collect::<Vec<_>>();
+            let numbers = match values.iter().map(|value| as_i128(value)).collect() {
+                Some(integers) => Numbers::Integers(integers),
+                None => Numbers::Floats(values.into_iter().filter_map(as_f64).collect()),
+            };
+            f(&numbers)
+        }))
+    }
+
+    fn aggregate(&self, name: &str, f: impl Fn(&[usize]) -> Option<Value>) -> ResultSet {
+        ResultSet {
+            column_names: vec![
+                self.result_set.column_names[self.key].clone(),
+                name.to_string(),
+            ],
+            column_types: vec![
+                self.result_set
+                    .column_types
+                    .get(self.key)
+                    .cloned()
+                    .unwrap_or(ColumnType::Unknown),
+                ColumnType::Number,
+            ],
+            rows: self
+                .groups
+                .iter()
+                .map(|(key, rows)| vec![key.clone(), f(rows).unwrap_or(Value::Null)])
+                .collect(),
+        }
+    }
+}
+
+/// Non-null values of a group, as integers if they all are, so they can be
+/// aggregated without losing precision
+enum Numbers {
+    Integers(Vec<i128>),
+    Floats(Vec<f64>),
+}
+
+impl Numbers {
+    fn to_f64(&self) -> Vec<f64> {
+        match self {
+            Numbers::Integers(values) => values.iter().map(|&n| n as f64).collect(),
+            Numbers::Floats(values) => values.clone(),
+        }
+    }
+}
+
+/// `n` as a JSON number, or as a string if it doesn't fit in one
+fn integer(n: i128) -> Value {
+    i64::try_from(n)
+        .map(Value::from)
+        .or_else(|_| u64::try_from(n).map(Value::from))
+        .unwrap_or_else(|_| Value::String(n.to_string()))
+}
+
+fn float(n: f64) -> Option<Value> {
+    Number::from_f64(n).map(Value::Number)
+}
+
+fn as_i128(value: &Value) -> Option<i128> {
+    match value {
+        Value::Number(number) => number
+            .as_i64()
+            .map(i128::from)
+            .or_else(|| number.as_u64().map(i128::from)),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+/// Numbers may come back as JSON numbers or as strings
+pub(crate) fn as_f64(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(number) => number.as_f64(),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn transfers(amounts: Vec<Value>) -> ResultSet {
+        ResultSet {
+            column_names: vec!["token".to_string(), "amount".to_string()],
+            column_types: vec![ColumnType::String, ColumnType::Number],
+            rows: amounts
+                .into_iter()
+                .map(|amount| vec![json!("eth"), amount])
+                .collect(),
+        }
+    }
+
+    fn aggregated(result_set: Option<ResultSet>) -> Value {
+        result_set.unwrap().rows[0][1].clone()
+    }
+
+    #[test]
+    fn integers_are_aggregated_exactly() {
+        let amounts = transfers(vec![
+            json!(9007199254740993i64),
+            json!(" 2"),
+            Value::Null,
+            json!("not a number"),
+        ]);
+        let group_by = amounts.group_by("token").unwrap();
+        assert_eq!(
+            aggregated(group_by.sum("amount")),
+            json!(9007199254740995i64)
+        );
+        assert_eq!(aggregated(group_by.min("amount")), json!(2));
+        assert_eq!(
+            aggregated(group_by.max("amount")),
+            json!(9007199254740993i64)
+        );
+        assert_eq!(aggregated(Some(group_by.count())), json!(4));
+    }
+
+    #[test]
+    fn sums_beyond_json_numbers_are_strings() {
+        let amounts = transfers(vec![json!(i64::MAX), json!(i64::MAX)]);
+        let group_by = amounts.group_by("token").unwrap();
+        assert_eq!(aggregated(group_by.sum("amount")), json!(u64::MAX - 1));
+
+        let wei = transfers(vec![json!(u64::MAX), json!("100000000000000000000")]);
+        let group_by = wei.group_by("token").unwrap();
+        assert_eq!(
+            aggregated(group_by.sum("amount")),
+            json!("118446744073709551615")
+        );
+    }
+
+    #[test]
+    fn fractions_and_nulls() {
+        let amounts = transfers(vec![json!(1.5), json!("2.5"), Value::Null]);
+        let group_by = amounts.group_by("token").unwrap();
+        assert_eq!(aggregated(group_by.sum("amount")), json!(4.0));
+        assert_eq!(aggregated(group_by.mean("amount")), json!(2.0));
+        assert_eq!(aggregated(group_by.min("amount")), json!(1.5));
+
+        let nulls = transfers(vec![Value::Null]);
+        let group_by = nulls.group_by("token").unwrap();
+        assert_eq!(aggregated(group_by.sum("amount")), json!(0));
+        assert_eq!(aggregated(group_by.mean("amount")), Value::Null);
+        assert!(group_by.sum("missing").is_none());
+    }
+}
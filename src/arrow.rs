@@ -0,0 +1,237 @@
+use crate::defaults::PAGE_SIZE;
+use crate::error::ApiError;
+use crate::flipside::Flipside;
+use crate::result_set::{as_integer, ResultSet};
+use crate::rpc::{ColumnType, GetQueryRunResultsResult};
+use arrow::array::{ArrayRef, BooleanBuilder, Float64Builder, Int64Builder, StringBuilder};
+use arrow::compute::cast;
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use arrow::error::ArrowError;
+use arrow::record_batch::RecordBatch;
+use futures::stream::{Stream, StreamExt};
+use serde_json::Value;
+use std::sync::Arc;
+
+#[derive(Debug, thiserror::Error)]
+pub enum RecordBatchError {
+    #[error(transparent)]
+    RpcError(#[from] ApiError),
+    #[error(transparent)]
+    ArrowError(#[from] ArrowError),
+}
+
+/// Arrow type of a result column. Numbers are `Float64`, dates UTC millisecond
+/// timestamps, and objects, arrays and unknown types JSON text. Result sets make
+/// number columns holding only integers `Int64` instead, see
+/// `ResultSet::arrow_schema`.
+pub fn data_type(column_type: &ColumnType) -> DataType {
+    match column_type {
+        ColumnType::Number => DataType::Float64,
+        ColumnType::Boolean => DataType::Boolean,
+        ColumnType::Date => DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into())),
+        ColumnType::String | ColumnType::Object | ColumnType::Array | ColumnType::Unknown => {
+            DataType::Utf8
+        }
+    }
+}
+
+/// Schema of results with these columns, every field nullable
+pub fn schema(column_names: &[String], column_types: &[ColumnType]) -> Schema {
+    Schema::new(
+        column_names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                let column_type = column_types.get(i).unwrap_or(&ColumnType::Unknown);
+                Field::new(name, data_type(column_type), true)
+            })
+            .collect::<Vec<_>>(),
+    )
+}
+
+impl ResultSet {
+    /// Schema of the rows, typed from `column_types`, with number columns holding
+    /// only integers as `Int64`
+    pub fn arrow_schema(&self) -> Schema {
+        let fields = schema(&self.column_names, &self.column_types)
+            .fields()
+            .iter()
+            .enumerate()
+            .map(|(i, field)| match field.data_type() {
+                DataType::Float64 if self.is_integral(i) => {
+                    Field::new(field.name(), DataType::Int64, true)
+                }
+                _ => field.as_ref().clone(),
+            })
+            .collect::<Vec<_>>();
+        Schema::new(fields)
+    }
+
+    /// Converts the rows to a record batch typed from `arrow_schema`. Values that
+    /// don't fit their column's type become nulls.
+    pub fn to_record_batch(&self) -> Result<RecordBatch, ArrowError> {
+        self.to_record_batch_with_schema(SchemaRef::new(self.arrow_schema()))
+    }
+
+    /// Converts the rows to a record batch of `schema`, e.g. the schema of another
+    /// page of the same results. Values that don't fit their column's type become
+    /// nulls, except numbers with a fraction in `Int64` columns, which fail.
+    pub fn to_record_batch_with_schema(
+        &self,
+        schema: SchemaRef,
+    ) -> Result<RecordBatch, ArrowError> {
+        let columns = schema
+            .fields()
+            .iter()
+            .enumerate()
+            .map(|(i, field)| {
+                column(
+                    self.rows
+                        .iter()
+                        .map(|row| row.get(i).unwrap_or(&Value::Null)),
+                    field,
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        RecordBatch::try_new(schema, columns)
+    }
+}
+
+impl TryFrom<GetQueryRunResultsResult> for RecordBatch {
+    type Error = ArrowError;
+
+    fn try_from(res: GetQueryRunResultsResult) -> Result<Self, Self::Error> {
+        ResultSet::from(res).to_record_batch()
+    }
+}
+
+fn column<'a>(
+    values: impl ExactSizeIterator<Item = &'a Value>,
+    field: &Field,
+) -> Result<ArrayRef, ArrowError> {
+    Ok(match field.data_type() {
+        DataType::Int64 => {
+            let mut builder = Int64Builder::with_capacity(values.len());
+            for value in values {
+                match as_integer(value) {
+                    Some(n) => builder.append_value(n),
+                    None if number(value).is_some() => {
+                        return Err(ArrowError::CastError(format!(
+                            "column `{}` holds integers, but got {}",
+                            field.name(),
+                            value
+                        )));
+                    }
+                    None => builder.append_null(),
+                }
+            }
+            Arc::new(builder.finish())
+        }
+        DataType::Float64 => {
+            let mut builder = Float64Builder::with_capacity(values.len());
+            for value in values {
+                builder.append_option(number(value));
+            }
+            Arc::new(builder.finish())
+        }
+        DataType::Boolean => {
+            let mut builder = BooleanBuilder::with_capacity(values.len());
+            for value in values {
+                builder.append_option(match value {
+                    Value::Bool(b) => Some(*b),
+                    Value::String(s) => s.to_lowercase().parse().ok(),
+                    _ => None,
+                });
+            }
+            Arc::new(builder.finish())
+        }
+        DataType::Timestamp(..) => cast(
+            &column(values, &Field::new(field.name(), DataType::Utf8, true))?,
+            field.data_type(),
+        )?,
+        _ => {
+            let mut builder = StringBuilder::new();
+            for value in values {
+                match value {
+                    Value::Null => builder.append_null(),
+                    Value::String(s) => builder.append_value(s),
+                    value => builder.append_value(value.to_string()),
+                }
+            }
+            Arc::new(builder.finish())
+        }
+    })
+}
+
+fn number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+impl Flipside {
+    /// Results of a run as record batches of up to `PAGE_SIZE` rows, one per page,
+    /// all with the schema of the first page. Fails on a later page holding a
+    /// number with a fraction in a column the first page only had integers in.
+    pub fn record_batches(
+        &self,
+        query_run_id: String,
+    ) -> impl Stream<Item = Result<RecordBatch, RecordBatchError>> + '_ {
+        let mut schema = None;
+        self.result_pages(query_run_id, PAGE_SIZE).map(move |page| {
+            let page = ResultSet::from(page?);
+            let schema = schema
+                .get_or_insert_with(|| SchemaRef::new(page.arrow_schema()))
+                .clone();
+            Ok(page.to_record_batch_with_schema(schema)?)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{AsArray, Float64Array, Int64Array};
+    use arrow::datatypes::{Float64Type, Int64Type};
+    use serde_json::json;
+
+    fn result_set(rows: Vec<Vec<Value>>) -> ResultSet {
+        ResultSet {
+            column_names: vec!["id".to_string(), "amount".to_string()],
+            column_types: vec![ColumnType::Number, ColumnType::Number],
+            rows,
+        }
+    }
+
+    #[test]
+    fn integral_number_columns_are_int64() {
+        let first = result_set(vec![
+            vec![json!(9007199254740993i64), json!(1)],
+            vec![json!("2"), json!(1.5)],
+            vec![Value::Null, Value::Null],
+        ]);
+        let batch = first.to_record_batch().unwrap();
+        assert_eq!(
+            batch.column(0).as_primitive::<Int64Type>(),
+            &Int64Array::from(vec![Some(9007199254740993), Some(2), None])
+        );
+        assert_eq!(
+            batch.column(1).as_primitive::<Float64Type>(),
+            &Float64Array::from(vec![Some(1.0), Some(1.5), None])
+        );
+
+        let schema = batch.schema();
+        let next = result_set(vec![vec![json!(3), json!(2)]]);
+        assert_eq!(
+            next.to_record_batch_with_schema(schema.clone())
+                .unwrap()
+                .schema(),
+            schema
+        );
+        let fractional = result_set(vec![vec![json!(3.5), json!(2)]]);
+        assert!(fractional.to_record_batch_with_schema(schema).is_err());
+    }
+}
@@ -0,0 +1,374 @@
+use crate::export::ExportError;
+use crate::flipside::Flipside;
+use crate::result_set::{as_integer, ResultSet};
+use crate::rpc::ColumnType;
+use crate::stats::parse_timestamp;
+use serde_json::{json, Value};
+use std::collections::hash_map::RandomState;
+use std::collections::HashSet;
+use std::hash::BuildHasher;
+use tokio::io::AsyncWrite;
+
+/// Avro type of a result column, a union with `null`. Numbers are a union of
+/// `long`, for integers, and `double`, dates `timestamp-millis` longs, and objects,
+/// arrays and unknown types JSON text.
+pub fn avro_type(column_type: &ColumnType) -> Value {
+    match column_type {
+        ColumnType::Number => json!(["null", "long", "double"]),
+        ColumnType::Boolean => json!(["null", "boolean"]),
+        ColumnType::Date => {
+            json!(["null", { "type": "long", "logicalType": "timestamp-millis" }])
+        }
+        ColumnType::String | ColumnType::Object | ColumnType::Array | ColumnType::Unknown => {
+            json!(["null", "string"])
+        }
+    }
+}
+
+/// Record schema of results with these columns. Column names are made valid Avro
+/// names by replacing other characters with `_`, and suffixed with `_2`, `_3`...
+/// when that makes them collide.
+pub fn avro_schema(column_names: &[String], column_types: &[ColumnType]) -> Value {
+    let mut names = HashSet::new();
+    let fields = column_names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            let column_type = column_types.get(i).unwrap_or(&ColumnType::Unknown);
+            let name = avro_name(name);
+            let name = (1..)
+                .map(|n| match n {
+                    1 => name.clone(),
+                    n => format!("{name}_{n}"),
+                })
+                .find(|name| names.insert(name.clone()))
+                .unwrap();
+            json!({
+                "name": name,
+                "type": avro_type(column_type),
+                "default": null,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    json!({ "type": "record", "name": "Row", "fields": fields })
+}
+
+fn avro_name(name: &str) -> String {
+    let mut name = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+    if !name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        name.insert(0, '_');
+    }
+    name
+}
+
+impl Flipside {
+    /// Writes the results of a run as an uncompressed Avro object container, one
+    /// block per page, with a schema derived from the first page's columns. Values
+    /// that don't fit their column's type become nulls. Returns the number of rows
+    /// written.
+    pub async fn export_avro(
+        &self,
+        query_run_id: String,
+        writer: impl AsyncWrite,
+    ) -> Result<usize, ExportError> {
+        let sync = sync_marker(&query_run_id);
+        let mut column_types = Vec::new();
+
+        self.export_pages(query_run_id, writer, |page, first, buf| {
+            if first {
+                column_types = page
+                    .column_names
+                    .iter()
+                    .enumerate()
+                    .map(|(i, _)| {
+                        page.column_types
+                            .get(i)
+                            .cloned()
+                            .unwrap_or(ColumnType::Unknown)
+                    })
+                    .collect();
+                write_header(buf, &avro_schema(&page.column_names, &column_types), &sync);
+            }
+            if !page.rows.is_empty() {
+                write_block(buf, page, &column_types, &sync);
+            }
+        })
+        .await
+    }
+}
+
+fn sync_marker(seed: &str) -> [u8; 16] {
+    let state = RandomState::new();
+    let mut sync = [0; 16];
+    sync[..8].copy_from_slice(&state.hash_one((seed, 0)).to_le_bytes());
+    sync[8..].copy_from_slice(&state.hash_one((seed, 1)).to_le_bytes());
+    sync
+}
+
+fn write_header(buf: &mut Vec<u8>, schema: &Value, sync: &[u8; 16]) {
+    buf.extend_from_slice(b"Obj\x01");
+    write_long(buf, 2);
+    write_bytes(buf, b"avro.schema");
+    write_bytes(buf, schema.to_string().as_bytes());
+    write_bytes(buf, b"avro.codec");
+    write_bytes(buf, b"null");
+    write_long(buf, 0);
+    buf.extend_from_slice(sync);
+}
+
+fn write_block(buf: &mut Vec<u8>, page: &ResultSet, column_types: &[ColumnType], sync: &[u8; 16]) {
+    let mut block = Vec::new();
+    for row in &page.rows {
+        for (i, column_type) in column_types.iter().enumerate() {
+            write_value(&mut block, row.get(i).unwrap_or(&Value::Null), column_type);
+        }
+    }
+
+    write_long(buf, page.rows.len() as i64);
+    write_long(buf, block.len() as i64);
+    buf.extend_from_slice(&block);
+    buf.extend_from_slice(sync);
+}
+
+fn write_value(buf: &mut Vec<u8>, value: &Value, column_type: &ColumnType) {
+    match column_type {
+        ColumnType::Number => {
+            let number = match value {
+                Value::Number(n) => n.as_f64(),
+                Value::String(s) => s.trim().parse().ok(),
+                _ => None,
+            };
+            match (as_integer(value), number) {
+                (Some(n), _) => {
+                    write_long(buf, 1);
+                    write_long(buf, n);
+                }
+                (None, Some(n)) => {
+                    write_long(buf, 2);
+                    buf.extend_from_slice(&n.to_le_bytes());
+                }
+                (None, None) => write_long(buf, 0),
+            }
+        }
+        ColumnType::Boolean => {
+            let boolean = match value {
+                Value::Bool(b) => Some(*b),
+                Value::String(s) => s.to_lowercase().parse().ok(),
+                _ => None,
+            };
+            write_union(buf, boolean, |buf, b| buf.push(b as u8));
+        }
+        ColumnType::Date => {
+            let millis = value
+                .as_str()
+                .and_then(parse_timestamp)
+                .map(|seconds| (seconds * 1000.0).round() as i64);
+            write_union(buf, millis, write_long);
+        }
+        _ => {
+            let text = match value {
+                Value::Null => None,
+                Value::String(s) => Some(s.clone()),
+                value => Some(value.to_string()),
+            };
+            write_union(buf, text, |buf, s| write_bytes(buf, s.as_bytes()));
+        }
+    }
+}
+
+fn write_union<T>(buf: &mut Vec<u8>, value: Option<T>, write: impl FnOnce(&mut Vec<u8>, T)) {
+    match value {
+        None => write_long(buf, 0),
+        Some(value) => {
+            write_long(buf, 1);
+            write(buf, value);
+        }
+    }
+}
+
+fn write_long(buf: &mut Vec<u8>, n: i64) {
+    let mut n = ((n << 1) ^ (n >> 63)) as u64;
+    while n >= 0x80 {
+        buf.push((n as u8) | 0x80);
+        n >>= 7;
+    }
+    buf.push(n as u8);
+}
+
+fn write_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    write_long(buf, bytes.len() as i64);
+    buf.extend_from_slice(bytes);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// Reads back an object container written by `write_header` and `write_block`,
+    /// following the schema it embeds
+    fn read_container(mut bytes: &[u8]) -> (Value, Vec<Vec<Value>>) {
+        fn long(bytes: &mut &[u8]) -> i64 {
+            let (mut n, mut shift) = (0u64, 0);
+            loop {
+                let byte = bytes[0];
+                *bytes = &bytes[1..];
+                n |= u64::from(byte & 0x7f) << shift;
+                shift += 7;
+                if byte < 0x80 {
+                    return (n >> 1) as i64 ^ -((n & 1) as i64);
+                }
+            }
+        }
+        fn take<'a>(bytes: &mut &'a [u8], len: usize) -> &'a [u8] {
+            let (taken, rest) = bytes.split_at(len);
+            *bytes = rest;
+            taken
+        }
+        fn value(bytes: &mut &[u8], schema: &Value) -> Value {
+            match schema {
+                Value::Array(branches) => {
+                    let branch = long(bytes) as usize;
+                    value(bytes, &branches[branch])
+                }
+                Value::Object(schema) => value(bytes, &schema["type"]),
+                Value::String(name) => match name.as_str() {
+                    "null" => Value::Null,
+                    "boolean" => json!(take(bytes, 1)[0] == 1),
+                    "long" => json!(long(bytes)),
+                    "double" => json!(f64::from_le_bytes(take(bytes, 8).try_into().unwrap())),
+                    "string" => {
+                        let len = long(bytes) as usize;
+                        json!(std::str::from_utf8(take(bytes, len)).unwrap())
+                    }
+                    name => panic!("unexpected type {name}"),
+                },
+                schema => panic!("unexpected schema {schema}"),
+            }
+        }
+
+        assert_eq!(take(&mut bytes, 4), b"Obj\x01");
+        let mut metadata = HashMap::new();
+        for _ in 0..long(&mut bytes) {
+            let len = long(&mut bytes) as usize;
+            let key = std::str::from_utf8(take(&mut bytes, len))
+                .unwrap()
+                .to_string();
+            let len = long(&mut bytes) as usize;
+            metadata.insert(key, take(&mut bytes, len).to_vec());
+        }
+        assert_eq!(long(&mut bytes), 0);
+        let sync = take(&mut bytes, 16).to_vec();
+        assert_eq!(metadata["avro.codec"], b"null");
+        let schema: Value = serde_json::from_slice(&metadata["avro.schema"]).unwrap();
+
+        let mut rows = Vec::new();
+        while !bytes.is_empty() {
+            let count = long(&mut bytes);
+            let len = long(&mut bytes) as usize;
+            let mut block = take(&mut bytes, len);
+            for _ in 0..count {
+                let fields = schema["fields"].as_array().unwrap();
+                rows.push(
+                    fields
+                        .iter()
+                        .map(|field| value(&mut block, &field["type"]))
+                        .collect(),
+                );
+            }
+            assert!(block.is_empty());
+            assert_eq!(take(&mut bytes, 16), sync);
+        }
+        (schema, rows)
+    }
+
+    #[test]
+    fn containers_round_trip() {
+        let page = ResultSet {
+            column_names: ["id", "amount", "ok", "at", "label", "Label"]
+                .map(String::from)
+                .to_vec(),
+            column_types: vec![
+                ColumnType::Number,
+                ColumnType::Number,
+                ColumnType::Boolean,
+                ColumnType::Date,
+                ColumnType::String,
+                ColumnType::Object,
+            ],
+            rows: vec![
+                vec![
+                    json!(9007199254740993i64),
+                    json!(1.5),
+                    json!(true),
+                    json!("2024-01-02T03:04:05.678Z"),
+                    json!("a"),
+                    json!({ "k": 1 }),
+                ],
+                vec![
+                    json!("-2"),
+                    json!("not a number"),
+                    Value::Null,
+                    Value::Null,
+                    Value::Null,
+                    Value::Null,
+                ],
+            ],
+        };
+        let sync = sync_marker("run");
+        let mut buf = Vec::new();
+        write_header(
+            &mut buf,
+            &avro_schema(&page.column_names, &page.column_types),
+            &sync,
+        );
+        write_block(&mut buf, &page, &page.column_types, &sync);
+
+        let (schema, rows) = read_container(&buf);
+        let names = schema["fields"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|field| field["name"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["id", "amount", "ok", "at", "label", "Label"]);
+        assert_eq!(
+            rows,
+            [
+                vec![
+                    json!(9007199254740993i64),
+                    json!(1.5),
+                    json!(true),
+                    json!(1704164645678i64),
+                    json!("a"),
+                    json!(r#"{"k":1}"#),
+                ],
+                vec![
+                    json!(-2),
+                    Value::Null,
+                    Value::Null,
+                    Value::Null,
+                    Value::Null,
+                    Value::Null
+                ],
+            ]
+        );
+    }
+
+    #[test]
+    fn sanitized_names_are_unique() {
+        let names = ["a b", "a_b", "a-b", "1"].map(String::from);
+        let schema = avro_schema(&names, &[]);
+        let names = schema["fields"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|field| field["name"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["a_b", "a_b_2", "a_b_3", "_1"]);
+    }
+}
@@ -0,0 +1,243 @@
+use crate::flipside::{Flipside, Query};
+use crate::result_set::Fnv64;
+use crate::retry::RunRetryPolicy;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::path::PathBuf;
+
+/// Runs a templated query over a range (block numbers, unix timestamps...) in
+/// chunks, recording each chunk's outcome in a ledger file so an interrupted
+/// backfill picks up where it left off.
+///
+/// `{start}` and `{end}` in the template are replaced by each chunk's bounds
+/// (start inclusive, end exclusive).
+#[derive(Debug, Clone)]
+pub struct Backfill {
+    pub template: String,
+    pub range: Range<u64>,
+    pub chunk_size: u64,
+    /// Re-runs of chunks that failed for a reason that may not happen again,
+    /// before they are marked as failed
+    pub retry_policy: RunRetryPolicy,
+    pub ledger_path: PathBuf,
+    /// Settings applied to every chunk's query, its SQL is replaced
+    pub query: Query,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum ChunkStatus {
+    Pending,
+    Done { query_run_id: String },
+    Failed { attempts: u32, error: String },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackfillChunk {
+    pub start: u64,
+    pub end: u64,
+    pub status: ChunkStatus,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BackfillLedger {
+    /// `Backfill::key` of the backfill the ledger belongs to
+    #[serde(default)]
+    pub key: String,
+    pub chunks: Vec<BackfillChunk>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct BackfillProgress {
+    pub total: usize,
+    pub done: usize,
+    pub failed: usize,
+}
+
+impl BackfillLedger {
+    pub fn progress(&self) -> BackfillProgress {
+        BackfillProgress {
+            total: self.chunks.len(),
+            done: self
+                .chunks
+                .iter()
+                .filter(|chunk| matches!(chunk.status, ChunkStatus::Done { .. }))
+                .count(),
+            failed: self
+                .chunks
+                .iter()
+                .filter(|chunk| matches!(chunk.status, ChunkStatus::Failed { .. }))
+                .count(),
+        }
+    }
+}
+
+impl Backfill {
+    pub fn new(
+        template: impl Into<String>,
+        range: Range<u64>,
+        chunk_size: u64,
+        ledger_path: impl Into<PathBuf>,
+    ) -> Self {
+        Self {
+            template: template.into(),
+            range,
+            chunk_size: chunk_size.max(1),
+            retry_policy: RunRetryPolicy::default(),
+            ledger_path: ledger_path.into(),
+            query: Query::default(),
+        }
+    }
+
+    pub fn retry_policy(mut self, retry_policy: RunRetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    pub fn query(mut self, query: Query) -> Self {
+        self.query = query;
+        self
+    }
+
+    pub fn chunks(&self) -> Vec<Range<u64>> {
+        let mut chunks = Vec::new();
+        let mut start = self.range.start;
+        while start < self.range.end {
+            let end = start.saturating_add(self.chunk_size).min(self.range.end);
+            chunks.push(start..end);
+            start = end;
+        }
+        chunks
+    }
+
+    /// Stable hash of the template, range and chunk size, identifying the ledger
+    pub fn key(&self) -> String {
+        let mut hasher = Fnv64::default();
+        hasher.write_value(&self.template);
+        hasher.write_value(&[self.range.start, self.range.end, self.chunk_size]);
+        format!("{:016x}", hasher.finish())
+    }
+
+    pub fn render(&self, chunk: &Range<u64>) -> String {
+        self.template
+            .replace("{start}", &chunk.start.to_string())
+            .replace("{end}", &chunk.end.to_string())
+    }
+
+    /// Loads the ledger from disk, or creates a fresh one if it doesn't exist.
+    /// Fails if the ledger belongs to another backfill, with a different template,
+    /// range or chunk size.
+    pub fn load_ledger(&self) -> io::Result<BackfillLedger> {
+        match fs::read(&self.ledger_path) {
+            Ok(bytes) => {
+                let ledger: BackfillLedger = serde_json::from_slice(&bytes)?;
+                if ledger.key != self.key() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "{} is the ledger of another backfill",
+                            self.ledger_path.display()
+                        ),
+                    ));
+                }
+                Ok(ledger)
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(BackfillLedger {
+                key: self.key(),
+                chunks: self
+                    .chunks()
+                    .into_iter()
+                    .map(|chunk| BackfillChunk {
+                        start: chunk.start,
+                        end: chunk.end,
+                        status: ChunkStatus::Pending,
+                    })
+                    .collect(),
+            }),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn save_ledger(&self, ledger: &BackfillLedger) -> io::Result<()> {
+        let tmp = self.ledger_path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(ledger)?)?;
+        fs::rename(tmp, &self.ledger_path)
+    }
+
+    /// Runs every chunk that isn't done yet, re-running failures `retry_policy`
+    /// allows, and saves the ledger after each chunk. Chunk failures are recorded in the returned ledger.
+    pub async fn run(
+        &self,
+        flipside: &Flipside,
+        mut on_progress: impl FnMut(BackfillProgress),
+    ) -> io::Result<BackfillLedger> {
+        let mut ledger = self.load_ledger()?;
+        on_progress(ledger.progress());
+
+        for i in 0..ledger.chunks.len() {
+            if matches!(ledger.chunks[i].status, ChunkStatus::Done { .. }) {
+                continue;
+            }
+
+            let range = ledger.chunks[i].start..ledger.chunks[i].end;
+            let query = Query {
+                sql: self.render(&range),
+                ..self.query.clone()
+            };
+
+            ledger.chunks[i].status = match self.retry_policy.run_counted(flipside, query).await {
+                (Ok(query_run), _) => ChunkStatus::Done {
+                    query_run_id: query_run.id,
+                },
+                (Err(err), attempts) => ChunkStatus::Failed {
+                    attempts,
+                    error: err.to_string(),
+                },
+            };
+
+            self.save_ledger(&ledger)?;
+            on_progress(ledger.progress());
+        }
+
+        Ok(ledger)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunks_cover_the_range_without_overflowing() {
+        let backfill = Backfill::new("{start}-{end}", 0..10, 4, "ledger.json");
+        assert_eq!(backfill.chunks(), [0..4, 4..8, 8..10]);
+        assert_eq!(backfill.render(&(4..8)), "4-8");
+
+        let near_max = Backfill::new("", u64::MAX - 5..u64::MAX, 4, "ledger.json");
+        assert_eq!(
+            near_max.chunks(),
+            [u64::MAX - 5..u64::MAX - 1, u64::MAX - 1..u64::MAX]
+        );
+        assert_eq!(
+            Backfill::new("", 0..u64::MAX, u64::MAX, "").chunks().len(),
+            1
+        );
+    }
+
+    #[test]
+    fn ledgers_of_other_backfills_are_rejected() {
+        let path = std::env::temp_dir().join(format!("backfill-{}.json", std::process::id()));
+        let backfill = Backfill::new("{start}", 0..10, 5, &path);
+        backfill
+            .save_ledger(&backfill.load_ledger().unwrap())
+            .unwrap();
+        assert_eq!(backfill.load_ledger().unwrap().chunks.len(), 2);
+
+        let other_range = Backfill::new("{start}", 0..20, 5, &path);
+        let err = other_range.load_ledger().unwrap_err();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,188 @@
+use crate::result_set::Row;
+use crate::rpc::ColumnType;
+use csv_core::{ReadRecordResult, Reader};
+use serde_json::{Number, Value};
+
+/// Parses a CSV payload into rows, converting each field according to its column type
+pub fn parse_rows(payload: &[u8], column_types: &[ColumnType]) -> Vec<Row> {
+    let mut reader = Reader::new();
+    let mut input = payload;
+    let mut rows = Vec::new();
+    let mut output = vec![0; 1024];
+    let mut ends = vec![0; column_types.len().max(1)];
+    let (mut out_len, mut ends_len) = (0, 0);
+
+    loop {
+        let (res, nin, nout, nend) =
+            reader.read_record(input, &mut output[out_len..], &mut ends[ends_len..]);
+        input = &input[nin..];
+        out_len += nout;
+        ends_len += nend;
+
+        match res {
+            ReadRecordResult::InputEmpty => {}
+            ReadRecordResult::OutputFull => output.resize(output.len() * 2, 0),
+            ReadRecordResult::OutputEndsFull => ends.resize(ends.len() * 2, 0),
+            ReadRecordResult::Record => {
+                let mut start = 0;
+                rows.push(
+                    ends[..ends_len]
+                        .iter()
+                        .enumerate()
+                        .map(|(i, &end)| {
+                            let field = &output[start..end];
+                            start = end;
+                            parse_field(field, column_types.get(i))
+                        })
+                        .collect(),
+                );
+                out_len = 0;
+                ends_len = 0;
+            }
+            ReadRecordResult::End => break,
+        }
+    }
+
+    rows
+}
+
+/// Parses a single CSV record
+pub fn parse_record(record: &str, column_types: &[ColumnType]) -> Row {
+    parse_rows(record.as_bytes(), column_types)
+        .into_iter()
+        .next()
+        .unwrap_or_default()
+}
+
+fn parse_field(field: &[u8], column_type: Option<&ColumnType>) -> Value {
+    let text = match std::str::from_utf8(field) {
+        Ok(text) => std::borrow::Cow::Borrowed(text),
+        Err(_) => String::from_utf8_lossy(field),
+    };
+
+    match column_type {
+        Some(ColumnType::String) | Some(ColumnType::Date) => Value::String(text.into_owned()),
+        _ if text.is_empty() => Value::Null,
+        Some(ColumnType::Number) => text
+            .parse::<i64>()
+            .map(Number::from)
+            .ok()
+            .or_else(|| text.parse::<f64>().ok().and_then(Number::from_f64))
+            .map(Value::Number)
+            .unwrap_or_else(|| Value::String(text.into_owned())),
+        Some(ColumnType::Boolean) if text.eq_ignore_ascii_case("true") => Value::Bool(true),
+        Some(ColumnType::Boolean) if text.eq_ignore_ascii_case("false") => Value::Bool(false),
+        Some(ColumnType::Object) | Some(ColumnType::Array) => {
+            serde_json::from_str(&text).unwrap_or_else(|_| Value::String(text.into_owned()))
+        }
+        _ => Value::String(text.into_owned()),
+    }
+}
+
+/// Appends a CSV record to `out`, quoting fields that contain a delimiter, quote or
+/// line break. Nulls are empty fields, objects and arrays JSON text.
+pub fn write_record<'a>(out: &mut Vec<u8>, fields: impl IntoIterator<Item = &'a Value>) {
+    for (i, field) in fields.into_iter().enumerate() {
+        if i > 0 {
+            out.push(b',');
+        }
+        match field {
+            Value::Null => {}
+            Value::String(s) => write_field(out, s),
+            field => write_field(out, &field.to_string()),
+        }
+    }
+    out.extend_from_slice(b"\r\n");
+}
+
+fn write_field(out: &mut Vec<u8>, field: &str) {
+    if !field.contains([',', '"', '\r', '\n']) {
+        out.extend_from_slice(field.as_bytes());
+        return;
+    }
+
+    out.push(b'"');
+    out.extend_from_slice(field.replace('"', "\"\"").as_bytes());
+    out.push(b'"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn parses_fields_by_column_type() {
+        let column_types = [
+            ColumnType::Number,
+            ColumnType::Number,
+            ColumnType::Boolean,
+            ColumnType::String,
+            ColumnType::Object,
+            ColumnType::Unknown,
+        ];
+        let rows = parse_rows(
+            b"42,1.5,TRUE,007,\"{\"\"a\"\":1}\",\r\n,x,maybe,\"a,b\",{,z\n",
+            &column_types,
+        );
+
+        assert_eq!(
+            rows,
+            [
+                vec![
+                    json!(42),
+                    json!(1.5),
+                    json!(true),
+                    json!("007"),
+                    json!({"a": 1}),
+                    json!(null)
+                ],
+                vec![
+                    json!(null),
+                    json!("x"),
+                    json!("maybe"),
+                    json!("a,b"),
+                    json!("{"),
+                    json!("z")
+                ],
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_quoted_line_breaks_in_a_record() {
+        let row = parse_record("\"line\nbreak\",\"say \"\"hi\"\"\"", &[]);
+        assert_eq!(row, [json!("line\nbreak"), json!("say \"hi\"")]);
+    }
+
+    #[test]
+    fn writes_quoted_records() {
+        let mut out = Vec::new();
+        write_record(
+            &mut out,
+            &[
+                json!("plain"),
+                json!("a,b"),
+                json!("say \"hi\""),
+                json!("line\nbreak"),
+                json!(null),
+                json!(1.5),
+                json!([1, 2]),
+            ],
+        );
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "plain,\"a,b\",\"say \"\"hi\"\"\",\"line\nbreak\",,1.5,\"[1,2]\"\r\n"
+        );
+    }
+
+    #[test]
+    fn written_records_parse_back() {
+        let row = vec![json!("a,b"), json!("\"quoted\""), json!("x\r\ny")];
+        let mut out = Vec::new();
+        write_record(&mut out, &row);
+
+        assert_eq!(parse_rows(&out, &[]), [row]);
+    }
+}
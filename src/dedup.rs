@@ -0,0 +1,100 @@
+use crate::result_set::Fnv64;
+use crate::rpc::CreateQueryRunParams;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::OnceCell;
+
+/// Runs created through a client, keyed by a hash of their creation parameters,
+/// so an identical resubmission attaches to the existing run instead of creating a
+/// duplicate. Entries are registered before the run is created, so submissions
+/// made while it is being created wait for it. This is an in-process memo: the API
+/// can't list runs, so runs created elsewhere, or whose creation response was
+/// lost, are never found.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RecentRuns(Arc<Mutex<HashMap<u64, RecentRun>>>);
+
+/// Id of the run once created, and when it was
+pub(crate) type RecentRun = Arc<OnceCell<(String, Instant)>>;
+
+impl RecentRuns {
+    pub(crate) fn key(params: &CreateQueryRunParams) -> u64 {
+        let mut tags = params.tags.iter().collect::<Vec<_>>();
+        tags.sort();
+
+        let mut hasher = Fnv64::default();
+        hasher.write_value(&params.sql);
+        hasher.write_value(&params.data_source);
+        hasher.write_value(&params.data_provider);
+        hasher.write_value(&tags);
+        hasher.write_value(&params.max_age_minutes);
+        hasher.write_value(&params.result_ttl_hours);
+        hasher.write_value(&params.statement_timeout_seconds);
+        hasher.finish()
+    }
+
+    /// Entry of `key`, registering a pending one if there's none created within
+    /// `window`
+    pub(crate) fn entry(&self, key: u64, window: Duration) -> RecentRun {
+        let mut runs = self.0.lock().unwrap();
+        runs.retain(|_, run| {
+            run.get()
+                .is_none_or(|(_, created_at)| created_at.elapsed() <= window)
+        });
+        runs.entry(key).or_default().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tags::Tags;
+
+    fn params() -> CreateQueryRunParams {
+        CreateQueryRunParams {
+            result_ttl_hours: 1,
+            max_age_minutes: 0,
+            sql: "select 1".to_string(),
+            tags: Tags::new(),
+            data_source: "snowflake-default".to_string(),
+            data_provider: "flipside".to_string(),
+            statement_timeout_seconds: None,
+        }
+    }
+
+    #[test]
+    fn key_covers_cache_ttl_and_timeout() {
+        let key = RecentRuns::key(&params());
+        assert_eq!(key, RecentRuns::key(&params()));
+
+        let cached = CreateQueryRunParams {
+            max_age_minutes: 60,
+            ..params()
+        };
+        let ttl = CreateQueryRunParams {
+            result_ttl_hours: 24,
+            ..params()
+        };
+        let timeout = CreateQueryRunParams {
+            statement_timeout_seconds: Some(30),
+            ..params()
+        };
+        for params in [cached, ttl, timeout] {
+            assert_ne!(key, RecentRuns::key(&params));
+        }
+    }
+
+    #[test]
+    fn pending_entries_are_shared_until_they_expire() {
+        let runs = RecentRuns::default();
+        let pending = runs.entry(1, Duration::ZERO);
+        assert!(Arc::ptr_eq(&pending, &runs.entry(1, Duration::ZERO)));
+
+        pending
+            .set(("run".to_string(), Instant::now() - Duration::from_secs(1)))
+            .unwrap();
+        let expired = runs.entry(1, Duration::ZERO);
+        assert!(!Arc::ptr_eq(&pending, &expired));
+        assert!(expired.get().is_none());
+    }
+}
@@ -1,3 +1,4 @@
+use crate::rpc::QueryFormat;
 use std::time::Duration;
 
 pub const API_BASE_URL: &str = "https://api-v2.flipsidecrypto.xyz/json-rpc";
@@ -6,7 +7,13 @@ pub const MAX_AGE_MINUTES: u64 = 0;
 pub const CACHED: bool = true;
 pub const DATA_PROVIDER: &str = "flipside";
 pub const DATA_SOURCE: &str = "snowflake-default";
+pub const SDK_PACKAGE: &str = "rust";
+pub const SDK_VERSION: &str = env!("CARGO_PKG_VERSION");
 pub const TIMEOUT: Duration = Duration::from_secs(20 * 60);
 pub const RETRY_INTERVAL: Duration = Duration::from_millis(500);
 pub const PAGE_SIZE: usize = 100000;
 pub const PAGE_NUMBER: usize = 1;
+pub const ESTIMATED_ROW_SIZE: u64 = 512;
+pub const NOT_READY_RETRIES: u32 = 5;
+pub const RESULT_FORMAT: QueryFormat = QueryFormat::Csv;
+pub const MAX_POLL_INTERVAL: Duration = Duration::from_secs(30);
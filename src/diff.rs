@@ -0,0 +1,216 @@
+use crate::error::ApiError;
+use crate::flipside::Flipside;
+use crate::result_set::{ResultSet, Row};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+
+/// Differences between two results matched on key columns
+#[derive(Debug, Clone, Default)]
+pub struct ResultDiff {
+    /// Rows only in the second result, in its order, with the first result's column
+    /// order
+    pub added: Vec<Row>,
+    /// Rows only in the first result, in its order
+    pub removed: Vec<Row>,
+    /// Rows in both whose other columns differ, in the first result's order
+    pub changed: Vec<ChangedRow>,
+}
+
+impl ResultDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ChangedRow {
+    pub before: Row,
+    /// In the first result's column order
+    pub after: Row,
+    /// Columns whose values differ
+    pub columns: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum DiffError {
+    #[error("no column named `{0}`")]
+    MissingColumn(String),
+    #[error("several rows have key {0:?}")]
+    DuplicateKey(Vec<String>),
+    #[error("columns {found:?} don't match {expected:?}")]
+    ColumnMismatch {
+        expected: Vec<String>,
+        found: Vec<String>,
+    },
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum DiffResultsError {
+    #[error(transparent)]
+    RpcError(#[from] ApiError),
+    #[error(transparent)]
+    DiffError(#[from] DiffError),
+}
+
+pub(crate) fn diff(
+    a: &ResultSet,
+    b: &ResultSet,
+    key_columns: &[&str],
+) -> Result<ResultDiff, DiffError> {
+    let mut sorted_a = a.column_names.clone();
+    let mut sorted_b = b.column_names.clone();
+    sorted_a.sort();
+    sorted_b.sort();
+    if sorted_a != sorted_b {
+        return Err(DiffError::ColumnMismatch {
+            expected: a.column_names.clone(),
+            found: b.column_names.clone(),
+        });
+    }
+
+    // Columns of `b` in the order of `a`
+    let b_columns = a
+        .column_names
+        .iter()
+        .map(|name| b.column_index(name).expect("columns match"))
+        .collect::<Vec<_>>();
+    let keys = key_columns
+        .iter()
+        .map(|column| {
+            a.column_index(column)
+                .ok_or_else(|| DiffError::MissingColumn(column.to_string()))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let key = |row: &Row, columns: &dyn Fn(usize) -> usize| {
+        keys.iter()
+            .map(|&i| row.get(columns(i)).unwrap_or(&Value::Null).to_string())
+            .collect::<Vec<_>>()
+    };
+
+    let mut index = HashMap::with_capacity(b.rows.len());
+    for (i, row) in b.rows.iter().enumerate() {
+        let key = key(row, &|i| b_columns[i]);
+        if index.insert(key.clone(), i).is_some() {
+            return Err(DiffError::DuplicateKey(key));
+        }
+    }
+
+    let mut seen = HashSet::with_capacity(a.rows.len());
+    let mut matched = vec![false; b.rows.len()];
+    let mut diff = ResultDiff::default();
+
+    for row in &a.rows {
+        let key = key(row, &|i| i);
+        if !seen.insert(key.clone()) {
+            return Err(DiffError::DuplicateKey(key));
+        }
+        let Some(&i) = index.get(&key) else {
+            diff.removed.push(row.clone());
+            continue;
+        };
+        matched[i] = true;
+
+        let after = reorder(&b.rows[i], &b_columns);
+        let columns = a
+            .column_names
+            .iter()
+            .enumerate()
+            .filter(|&(j, _)| row.get(j).unwrap_or(&Value::Null) != &after[j])
+            .map(|(_, name)| name.clone())
+            .collect::<Vec<_>>();
+        if !columns.is_empty() {
+            diff.changed.push(ChangedRow {
+                before: row.clone(),
+                after,
+                columns,
+            });
+        }
+    }
+
+    diff.added = b
+        .rows
+        .iter()
+        .zip(matched)
+        .filter(|(_, matched)| !matched)
+        .map(|(row, _)| reorder(row, &b_columns))
+        .collect();
+    Ok(diff)
+}
+
+fn reorder(row: &Row, columns: &[usize]) -> Row {
+    columns
+        .iter()
+        .map(|&i| row.get(i).cloned().unwrap_or(Value::Null))
+        .collect()
+}
+
+impl Flipside {
+    /// Fetches all the results of two runs of the same query, regardless of the
+    /// client's `max_rows`, and compares them, rows being matched on `key_columns`,
+    /// which must be unique in both
+    pub async fn diff_results(
+        &self,
+        run_a: &str,
+        run_b: &str,
+        key_columns: &[&str],
+    ) -> Result<ResultDiff, DiffResultsError> {
+        let a = self.collect_all_results(run_a.to_string()).await?;
+        let b = self.collect_all_results(run_b.to_string()).await?;
+        Ok(a.diff(&b, key_columns)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn result_set(column_names: &[&str], rows: Vec<Value>) -> ResultSet {
+        ResultSet::new(
+            column_names.iter().map(|name| name.to_string()).collect(),
+            Vec::new(),
+            rows,
+        )
+    }
+
+    #[test]
+    fn reports_added_removed_and_changed_rows() {
+        let a = result_set(
+            &["id", "v"],
+            vec![json!([1, "a"]), json!([2, "b"]), json!([3, "c"])],
+        );
+        // Same columns in another order
+        let b = result_set(
+            &["v", "id"],
+            vec![json!(["a", 1]), json!(["B", 2]), json!(["d", 4])],
+        );
+        let diff = a.diff(&b, &["id"]).unwrap();
+
+        assert_eq!(diff.added, [vec![json!(4), json!("d")]]);
+        assert_eq!(diff.removed, [vec![json!(3), json!("c")]]);
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(diff.changed[0].before, [json!(2), json!("b")]);
+        assert_eq!(diff.changed[0].after, [json!(2), json!("B")]);
+        assert_eq!(diff.changed[0].columns, ["v"]);
+        assert!(a.diff(&a, &["id"]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_duplicate_keys_and_other_columns() {
+        let a = result_set(&["id", "v"], vec![json!([1, "a"]), json!([1, "b"])]);
+        assert_eq!(
+            a.diff(&a, &["id"]).unwrap_err(),
+            DiffError::DuplicateKey(vec!["1".to_string()])
+        );
+        assert_eq!(
+            a.diff(&a, &["missing"]).unwrap_err(),
+            DiffError::MissingColumn("missing".to_string())
+        );
+
+        let b = result_set(&["id", "w"], Vec::new());
+        assert!(matches!(
+            a.diff(&b, &["id"]),
+            Err(DiffError::ColumnMismatch { .. })
+        ));
+    }
+}
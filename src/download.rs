@@ -0,0 +1,159 @@
+use crate::flipside::Flipside;
+use crate::rpc::QueryRun;
+use futures::stream::{self, StreamExt, TryStreamExt};
+use reqwest::{Client, Response};
+use sha2::{Digest, Sha256};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use tokio::fs::{self, File};
+use tokio::io::AsyncWriteExt;
+
+/// Client shared by every download, so connections are pooled across calls
+static HTTP_CLIENT: OnceLock<Client> = OnceLock::new();
+
+#[derive(Debug, Clone)]
+pub struct DownloadedFile {
+    pub name: String,
+    pub path: PathBuf,
+    pub size: u64,
+    /// Hex SHA-256 of the contents
+    pub sha256: String,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum DownloadError {
+    #[error("result files at `{0}` aren't reachable over HTTP")]
+    UnsupportedPath(String),
+    #[error("invalid result file name `{0}`")]
+    InvalidFileName(String),
+    #[error("failed to download `{name}`")]
+    HttpError {
+        name: String,
+        #[source]
+        error: reqwest::Error,
+    },
+    #[error("failed to write `{name}`")]
+    IoError {
+        name: String,
+        #[source]
+        error: io::Error,
+    },
+    #[error("`{name}` is {found} bytes instead of {expected}")]
+    SizeMismatch {
+        name: String,
+        expected: u64,
+        found: u64,
+    },
+}
+
+impl Flipside {
+    /// Downloads the result files of a run, listed in `file_names` under `path`,
+    /// into `dir`, `max_parallel` at a time. Each file is checked against the size
+    /// announced by the server and hashed as it is written. Returns the files in
+    /// `file_names` order. A file that fails to download is removed.
+    pub async fn download_result_files(
+        &self,
+        query_run: &QueryRun,
+        dir: impl AsRef<Path>,
+        max_parallel: usize,
+    ) -> Result<Vec<DownloadedFile>, DownloadError> {
+        let base_url = query_run.path.trim_end_matches('/');
+        if !base_url.starts_with("https://") && !base_url.starts_with("http://") {
+            return Err(DownloadError::UnsupportedPath(query_run.path.clone()));
+        }
+        let names: Vec<String> = query_run
+            .file_names
+            .clone()
+            .map(Into::into)
+            .unwrap_or_default();
+        let client = HTTP_CLIENT.get_or_init(Client::new);
+
+        stream::iter(names)
+            .map(|name| {
+                let url = format!("{}/{}", base_url, name);
+                let path = Path::new(&name)
+                    .file_name()
+                    .map(|file_name| dir.as_ref().join(file_name));
+                async move {
+                    let path = path.ok_or_else(|| DownloadError::InvalidFileName(name.clone()))?;
+                    download_file(client, &url, name, path).await
+                }
+            })
+            .buffered(max_parallel.max(1))
+            .try_collect()
+            .await
+    }
+}
+
+async fn download_file(
+    client: &Client,
+    url: &str,
+    name: String,
+    path: PathBuf,
+) -> Result<DownloadedFile, DownloadError> {
+    let response = client
+        .get(url)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|error| DownloadError::HttpError {
+            name: name.clone(),
+            error,
+        })?;
+
+    let res = write_file(response, name, &path).await;
+    if res.is_err() {
+        let _ = fs::remove_file(&path).await;
+    }
+    res
+}
+
+/// Streams `response` into a new file at `path`, leaving it partially written if
+/// it fails
+async fn write_file(
+    response: Response,
+    name: String,
+    path: &Path,
+) -> Result<DownloadedFile, DownloadError> {
+    let http_error = |error| DownloadError::HttpError {
+        name: name.clone(),
+        error,
+    };
+    let io_error = |error| DownloadError::IoError {
+        name: name.clone(),
+        error,
+    };
+    let expected = response.content_length();
+
+    let mut file = File::create(path).await.map_err(io_error)?;
+    let mut hasher = Sha256::new();
+    let mut size = 0;
+    let mut chunks = response.bytes_stream();
+    while let Some(chunk) = chunks.next().await {
+        let chunk = chunk.map_err(http_error)?;
+        hasher.update(&chunk);
+        file.write_all(&chunk).await.map_err(io_error)?;
+        size += chunk.len() as u64;
+    }
+    file.flush().await.map_err(io_error)?;
+
+    if let Some(expected) = expected.filter(|&expected| expected != size) {
+        return Err(DownloadError::SizeMismatch {
+            name,
+            expected,
+            found: size,
+        });
+    }
+
+    Ok(DownloadedFile {
+        name,
+        path: path.to_path_buf(),
+        size,
+        sha256: hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect(),
+    })
+}
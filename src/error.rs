@@ -0,0 +1,206 @@
+use crate::flipside::ClientError;
+use jsonrpsee::http_client::transport::Error as TransportError;
+use serde_json::Value;
+use std::time::Duration;
+
+/// Failure of an API call, classified from the JSON-RPC error code or HTTP status
+#[derive(Debug, thiserror::Error)]
+pub enum ApiError {
+    /// Too many requests, `retry_after` is how long the API asked to wait if it said
+    #[error("rate limited: {message}")]
+    RateLimited {
+        message: String,
+        retry_after: Option<Duration>,
+    },
+    /// The query run, statement or method doesn't exist
+    #[error("not found: {message}")]
+    NotFound { message: String },
+    /// The request was rejected, e.g. invalid parameters or SQL
+    #[error("request rejected ({code}): {message}")]
+    UserError { code: i32, message: String },
+    #[error("server error ({code}): {message}")]
+    ServerError { code: i32, message: String },
+    /// Network failure or request timeout
+    #[error(transparent)]
+    Transport(ClientError),
+    #[error(transparent)]
+    Other(ClientError),
+}
+
+/// Client-side rate limit that isn't a finite number of at least one call a day
+#[derive(Debug, Clone, Copy, PartialEq, thiserror::Error)]
+#[error("rate must be finite and at least one call a day, got {0}")]
+pub struct InvalidRate(pub f64);
+
+pub const INVALID_PARAMS_CODE: i32 = -32602;
+pub const METHOD_NOT_FOUND_CODE: i32 = -32601;
+pub const INTERNAL_ERROR_CODE: i32 = -32603;
+
+impl From<ClientError> for ApiError {
+    fn from(err: ClientError) -> Self {
+        match err {
+            ClientError::Call(err) => {
+                let code = err.code();
+                let message = err.message().to_string();
+                let lowercase = message.to_lowercase();
+
+                if is_rate_limit_message(&lowercase) {
+                    ApiError::RateLimited {
+                        retry_after: parse_retry_after(&lowercase, err.data().map(|d| d.get())),
+                        message,
+                    }
+                } else if code == METHOD_NOT_FOUND_CODE {
+                    ApiError::NotFound { message }
+                } else if code == INTERNAL_ERROR_CODE || (-32099..=-32000).contains(&code) {
+                    ApiError::ServerError { code, message }
+                } else {
+                    ApiError::UserError { code, message }
+                }
+            }
+            ClientError::Transport(err) => {
+                let status_code = match err.downcast_ref::<TransportError>() {
+                    Some(TransportError::Rejected { status_code }) => *status_code,
+                    _ => return ApiError::Transport(ClientError::Transport(err)),
+                };
+                let message = err.to_string();
+
+                match status_code {
+                    429 => ApiError::RateLimited {
+                        message,
+                        retry_after: None,
+                    },
+                    404 => ApiError::NotFound { message },
+                    400..=499 => ApiError::UserError {
+                        code: status_code.into(),
+                        message,
+                    },
+                    _ => ApiError::ServerError {
+                        code: status_code.into(),
+                        message,
+                    },
+                }
+            }
+            ClientError::RequestTimeout => ApiError::Transport(err),
+            err => ApiError::Other(err),
+        }
+    }
+}
+
+/// `Some` if `err` is a rate limit rejection, holding the delay the API asked for
+/// if any
+pub(crate) fn rate_limit(err: &ClientError) -> Option<Option<Duration>> {
+    match err {
+        ClientError::Call(err) => {
+            let message = err.message().to_lowercase();
+            is_rate_limit_message(&message)
+                .then(|| parse_retry_after(&message, err.data().map(|d| d.get())))
+        }
+        ClientError::Transport(err) => match err.downcast_ref::<TransportError>() {
+            Some(TransportError::Rejected { status_code: 429 }) => Some(None),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn is_rate_limit_message(message: &str) -> bool {
+    message.contains("rate limit") || message.contains("too many requests")
+}
+
+/// Reads the delay from a `retry_after`/`retryAfter` field (in seconds) of the
+/// error data, or from a "retry after N" or "try again in N" message
+fn parse_retry_after(message: &str, data: Option<&str>) -> Option<Duration> {
+    let from_data = data
+        .and_then(|data| serde_json::from_str::<Value>(data).ok())
+        .and_then(|data| {
+            let value = data.get("retry_after").or_else(|| data.get("retryAfter"))?;
+            match value {
+                Value::Number(seconds) => seconds.as_f64(),
+                Value::String(seconds) => seconds.trim().parse().ok(),
+                _ => None,
+            }
+        });
+    let from_message = || {
+        ["retry after", "try again in"].iter().find_map(|prefix| {
+            let rest = &message[message.find(prefix)? + prefix.len()..];
+            let seconds = rest.split_whitespace().next()?;
+            seconds
+                .trim_end_matches(|c: char| !c.is_ascii_digit())
+                .parse()
+                .ok()
+        })
+    };
+
+    from_data
+        .or_else(from_message)
+        .filter(|seconds| seconds.is_finite() && *seconds >= 0.0)
+        .map(Duration::from_secs_f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonrpsee::types::ErrorObjectOwned;
+
+    fn call_error(code: i32, message: &str, data: Option<Value>) -> ClientError {
+        ClientError::Call(ErrorObjectOwned::owned(code, message, data))
+    }
+
+    #[test]
+    fn call_errors_are_classified_by_code() {
+        assert!(matches!(
+            ApiError::from(call_error(METHOD_NOT_FOUND_CODE, "no such method", None)),
+            ApiError::NotFound { .. }
+        ));
+        assert!(matches!(
+            ApiError::from(call_error(INVALID_PARAMS_CODE, "table not found", None)),
+            ApiError::UserError {
+                code: INVALID_PARAMS_CODE,
+                ..
+            }
+        ));
+        assert!(matches!(
+            ApiError::from(call_error(-32000, "boom", None)),
+            ApiError::ServerError { code: -32000, .. }
+        ));
+    }
+
+    #[test]
+    fn rate_limits_carry_the_requested_delay() {
+        let from_data = call_error(
+            -32000,
+            "Rate limit exceeded",
+            Some(serde_json::json!({ "retryAfter": "2.5" })),
+        );
+        assert!(matches!(
+            ApiError::from(from_data),
+            ApiError::RateLimited { retry_after: Some(delay), .. } if delay == Duration::from_millis(2500)
+        ));
+
+        let from_message = call_error(-32000, "Too many requests, try again in 3s", None);
+        assert_eq!(
+            rate_limit(&from_message),
+            Some(Some(Duration::from_secs(3)))
+        );
+        assert_eq!(rate_limit(&call_error(-32000, "boom", None)), None);
+    }
+
+    #[test]
+    fn rejected_requests_are_classified_by_status() {
+        let rejected = |status_code| {
+            ApiError::from(ClientError::Transport(
+                TransportError::Rejected { status_code }.into(),
+            ))
+        };
+        assert!(matches!(rejected(429), ApiError::RateLimited { .. }));
+        assert!(matches!(rejected(404), ApiError::NotFound { .. }));
+        assert!(matches!(
+            rejected(401),
+            ApiError::UserError { code: 401, .. }
+        ));
+        assert!(matches!(
+            rejected(502),
+            ApiError::ServerError { code: 502, .. }
+        ));
+    }
+}
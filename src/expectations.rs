@@ -0,0 +1,169 @@
+use crate::result_set::ResultSet;
+use serde_json::Value;
+use std::collections::HashSet;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExpectationFailure {
+    pub expectation: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExpectationReport {
+    pub checked: usize,
+    pub failures: Vec<ExpectationFailure>,
+}
+
+impl ExpectationReport {
+    pub fn is_ok(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// Data-quality checks on a result set, see `ResultSet::expect`
+#[derive(Debug, Clone)]
+pub struct Expectations<'a> {
+    result_set: &'a ResultSet,
+    report: ExpectationReport,
+}
+
+impl<'a> Expectations<'a> {
+    pub(crate) fn new(result_set: &'a ResultSet) -> Self {
+        Self {
+            result_set,
+            report: ExpectationReport::default(),
+        }
+    }
+
+    fn check(mut self, expectation: String, failure: Option<String>) -> Self {
+        self.report.checked += 1;
+        if let Some(reason) = failure {
+            self.report.failures.push(ExpectationFailure {
+                expectation,
+                reason,
+            });
+        }
+        self
+    }
+
+    fn column_values(&self, column: &str) -> Option<impl Iterator<Item = &'a Value>> {
+        let index = self.result_set.column_index(column)?;
+        Some(
+            self.result_set
+                .rows
+                .iter()
+                .map(move |row| row.get(index).unwrap_or(&Value::Null)),
+        )
+    }
+
+    pub fn expect_row_count_between(self, min: usize, max: usize) -> Self {
+        let count = self.result_set.len();
+        self.check(
+            format!("row count between {} and {}", min, max),
+            (count < min || count > max).then(|| format!("got {} rows", count)),
+        )
+    }
+
+    pub fn expect_column(self, column: &str) -> Self {
+        let failure = self
+            .result_set
+            .column_index(column)
+            .is_none()
+            .then(|| "column does not exist".to_string());
+        self.check(format!("column {} exists", column), failure)
+    }
+
+    pub fn expect_no_nulls(self, column: &str) -> Self {
+        let failure = match self.column_values(column) {
+            None => Some("column does not exist".to_string()),
+            Some(values) => {
+                let nulls = values.filter(|value| value.is_null()).count();
+                (nulls > 0).then(|| format!("{} null values", nulls))
+            }
+        };
+        self.check(format!("no nulls in {}", column), failure)
+    }
+
+    pub fn expect_unique(self, column: &str) -> Self {
+        let failure = match self.column_values(column) {
+            None => Some("column does not exist".to_string()),
+            Some(values) => {
+                let mut seen = HashSet::new();
+                let duplicates = values
+                    .filter(|value| !seen.insert(value.to_string()))
+                    .count();
+                (duplicates > 0).then(|| format!("{} duplicate values", duplicates))
+            }
+        };
+        self.check(format!("unique values in {}", column), failure)
+    }
+
+    pub fn report(self) -> ExpectationReport {
+        self.report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rpc::ColumnType;
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    fn addresses() -> ResultSet {
+        ResultSet {
+            column_names: vec!["address".to_string(), "label".to_string()],
+            column_types: vec![ColumnType::String; 2],
+            rows: vec![
+                vec![json!("0xa"), json!("exchange")],
+                vec![json!("0xb"), Value::Null],
+                vec![json!("0xa"), Value::Null],
+            ],
+        }
+    }
+
+    #[test]
+    fn failures_are_reported_with_their_reason() {
+        let addresses = addresses();
+        let report = addresses
+            .expect()
+            .expect_row_count_between(1, 2)
+            .expect_column("address")
+            .expect_column("balance")
+            .expect_no_nulls("label")
+            .expect_unique("address")
+            .expect_unique("balance")
+            .report();
+
+        assert_eq!(report.checked, 6);
+        assert!(!report.is_ok());
+        let failures = report
+            .failures
+            .iter()
+            .map(|failure| (failure.expectation.as_str(), failure.reason.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            failures,
+            vec![
+                ("row count between 1 and 2", "got 3 rows"),
+                ("column balance exists", "column does not exist"),
+                ("no nulls in label", "2 null values"),
+                ("unique values in address", "1 duplicate values"),
+                ("unique values in balance", "column does not exist"),
+            ]
+        );
+    }
+
+    #[test]
+    fn passing_expectations_are_counted() {
+        let addresses = addresses();
+        let report = addresses
+            .expect()
+            .expect_row_count_between(3, 3)
+            .expect_no_nulls("address")
+            .report();
+
+        assert_eq!(report.checked, 2);
+        assert!(report.is_ok());
+    }
+}
@@ -0,0 +1,237 @@
+use crate::csv::write_record;
+use crate::defaults::PAGE_SIZE;
+use crate::error::ApiError;
+use crate::flipside::Flipside;
+use crate::result_set::ResultSet;
+use futures::sink::Sink;
+use futures::stream::StreamExt;
+use serde_json::Value;
+use std::error::Error;
+use std::io;
+use std::pin::{pin, Pin};
+use std::task::{Context, Poll};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ExportError {
+    #[error(transparent)]
+    RpcError(#[from] ApiError),
+    #[error("failed to write the results")]
+    IoError(#[source] io::Error),
+}
+
+/// Format of `Flipside::export`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    Ndjson,
+    PgCopy,
+    #[cfg(feature = "avro")]
+    Avro,
+}
+
+impl ExportFormat {
+    /// Conventional file extension, without the dot
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Ndjson => "ndjson",
+            ExportFormat::PgCopy => "tsv",
+            #[cfg(feature = "avro")]
+            ExportFormat::Avro => "avro",
+        }
+    }
+}
+
+impl Flipside {
+    /// Writes the results of a run in `format`. Returns the number of rows written.
+    pub async fn export(
+        &self,
+        query_run_id: String,
+        writer: impl AsyncWrite,
+        format: ExportFormat,
+    ) -> Result<usize, ExportError> {
+        match format {
+            ExportFormat::Csv => self.export_csv(query_run_id, writer).await,
+            ExportFormat::Ndjson => self.export_ndjson(query_run_id, writer).await,
+            ExportFormat::PgCopy => self.export_pg_copy(query_run_id, writer).await,
+            #[cfg(feature = "avro")]
+            ExportFormat::Avro => self.export_avro(query_run_id, writer).await,
+        }
+    }
+
+    /// Sends the results of a run in `format` to `sink`, one chunk per page. The
+    /// next page is only fetched once the sink has accepted the previous chunk, so
+    /// a slow sink pauses fetching and at most a page is held in memory besides
+    /// what the sink buffers. The sink is closed at the end. Returns the number of
+    /// rows written.
+    pub async fn write_results<S>(
+        &self,
+        query_run_id: String,
+        sink: S,
+        format: ExportFormat,
+    ) -> Result<usize, ExportError>
+    where
+        S: Sink<Vec<u8>>,
+        S::Error: Into<Box<dyn Error + Send + Sync>>,
+    {
+        let mut writer = SinkWriter(pin!(sink));
+        let rows = self.export(query_run_id, &mut writer, format).await?;
+        writer.shutdown().await.map_err(ExportError::IoError)?;
+        Ok(rows)
+    }
+
+    /// Writes the results of a run as CSV, with a header row of column names, one
+    /// page at a time. Returns the number of rows written.
+    pub async fn export_csv(
+        &self,
+        query_run_id: String,
+        writer: impl AsyncWrite,
+    ) -> Result<usize, ExportError> {
+        self.export_pages(query_run_id, writer, |page, first, buf| {
+            if first {
+                let header = page
+                    .column_names
+                    .iter()
+                    .map(|name| Value::String(name.clone()))
+                    .collect::<Vec<_>>();
+                write_record(buf, &header);
+            }
+            for row in &page.rows {
+                write_record(buf, row);
+            }
+        })
+        .await
+    }
+
+    /// Writes the results of a run as JSON Lines, one object per row keyed by
+    /// column name in column order. Returns the number of rows written.
+    pub async fn export_ndjson(
+        &self,
+        query_run_id: String,
+        writer: impl AsyncWrite,
+    ) -> Result<usize, ExportError> {
+        self.export_pages(query_run_id, writer, |page, _, buf| {
+            let keys = page
+                .column_names
+                .iter()
+                .map(|name| Value::String(name.clone()).to_string())
+                .collect::<Vec<_>>();
+            for row in &page.rows {
+                buf.push(b'{');
+                for (i, key) in keys.iter().enumerate() {
+                    if i > 0 {
+                        buf.push(b',');
+                    }
+                    buf.extend_from_slice(key.as_bytes());
+                    buf.push(b':');
+                    buf.extend_from_slice(
+                        row.get(i).unwrap_or(&Value::Null).to_string().as_bytes(),
+                    );
+                }
+                buf.extend_from_slice(b"}\n");
+            }
+        })
+        .await
+    }
+
+    /// Writes the results of a run in PostgreSQL's `COPY ... FROM STDIN` text
+    /// format, tab-separated with `\N` nulls, to be streamed to a copy-in of a
+    /// table with the same columns. Returns the number of rows written.
+    pub async fn export_pg_copy(
+        &self,
+        query_run_id: String,
+        writer: impl AsyncWrite,
+    ) -> Result<usize, ExportError> {
+        self.export_pages(query_run_id, writer, |page, _, buf| {
+            for row in &page.rows {
+                for (i, value) in row.iter().enumerate() {
+                    if i > 0 {
+                        buf.push(b'\t');
+                    }
+                    match value {
+                        Value::Null => buf.extend_from_slice(b"\\N"),
+                        Value::String(s) => write_copy_field(buf, s),
+                        value => write_copy_field(buf, &value.to_string()),
+                    }
+                }
+                buf.push(b'\n');
+            }
+        })
+        .await
+    }
+
+    pub(crate) async fn export_pages(
+        &self,
+        query_run_id: String,
+        writer: impl AsyncWrite,
+        mut encode: impl FnMut(&ResultSet, bool, &mut Vec<u8>),
+    ) -> Result<usize, ExportError> {
+        let mut writer = pin!(writer);
+        let mut pages = pin!(self.result_pages(query_run_id, PAGE_SIZE));
+        let mut first = true;
+        let mut rows = 0;
+        let mut buf = Vec::new();
+
+        while let Some(page) = pages.next().await {
+            let page = ResultSet::from(page?);
+            buf.clear();
+            encode(&page, first, &mut buf);
+            first = false;
+
+            rows += page.rows.len();
+            writer.write_all(&buf).await.map_err(ExportError::IoError)?;
+        }
+
+        writer.flush().await.map_err(ExportError::IoError)?;
+        Ok(rows)
+    }
+}
+
+/// Forwards every write to a sink as one chunk
+struct SinkWriter<'a, S>(Pin<&'a mut S>);
+
+impl<S> AsyncWrite for SinkWriter<'_, S>
+where
+    S: Sink<Vec<u8>>,
+    S::Error: Into<Box<dyn Error + Send + Sync>>,
+{
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.0.as_mut().poll_ready(cx) {
+            Poll::Ready(Ok(())) => {}
+            Poll::Ready(Err(err)) => return Poll::Ready(Err(io::Error::other(err))),
+            Poll::Pending => return Poll::Pending,
+        }
+        Poll::Ready(
+            self.0
+                .as_mut()
+                .start_send(buf.to_vec())
+                .map(|()| buf.len())
+                .map_err(io::Error::other),
+        )
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.0.as_mut().poll_flush(cx).map_err(io::Error::other)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.0.as_mut().poll_close(cx).map_err(io::Error::other)
+    }
+}
+
+fn write_copy_field(buf: &mut Vec<u8>, field: &str) {
+    for b in field.bytes() {
+        match b {
+            b'\\' => buf.extend_from_slice(b"\\\\"),
+            b'\t' => buf.extend_from_slice(b"\\t"),
+            b'\n' => buf.extend_from_slice(b"\\n"),
+            b'\r' => buf.extend_from_slice(b"\\r"),
+            b => buf.push(b),
+        }
+    }
+}
@@ -1,155 +1,1025 @@
+use crate::dedup::RecentRuns;
 use crate::defaults::{
-    API_BASE_URL, DATA_PROVIDER, DATA_SOURCE, MAX_AGE_MINUTES, RETRY_INTERVAL, TIMEOUT, TTL_MINUTES,
+    API_BASE_URL, CACHED, DATA_PROVIDER, DATA_SOURCE, ESTIMATED_ROW_SIZE, MAX_AGE_MINUTES,
+    MAX_POLL_INTERVAL, NOT_READY_RETRIES, PAGE_NUMBER, PAGE_SIZE, RESULT_FORMAT, RETRY_INTERVAL,
+    SDK_PACKAGE, SDK_VERSION, TIMEOUT, TTL_MINUTES,
 };
+use crate::error::{ApiError, InvalidRate};
+use crate::handle::{QueryRunHandle, RuntimeHistory};
+use crate::pagination::PaginationState;
+use crate::poll::{Linear, PollStrategy};
+use crate::poller::SharedPoller;
+use crate::rate_limit::RateLimiter;
+use crate::registry::{QueryRegistry, RegistryError};
+use crate::result_set::{Fingerprinter, LazyResultSet, ResultSet, Row};
+use crate::retry::{RetryPolicy, RunRetryPolicy};
 use crate::rpc::{
-    CreateQueryRunParams, FilterKey, GetQueryRunResultsParams, GetQueryRunResultsResult,
-    Pagination, QueryFormat, QueryRun, QueryRunIdParams, QueryState, RpcClient, SortBy,
+    CreateQueryRunParams, Filter, GetQueryRunResult, GetQueryRunResultsParams,
+    GetQueryRunResultsResult, Pagination, PaginationDetails, QueryFormat, QueryRun,
+    QueryRunIdParams, RawRpcClient, RpcClient, SortBy, SqlStatement, SqlStatementIdParams,
 };
+use crate::schema::{Schema, SchemaDiff};
+use crate::spill::{SpillWriter, SpilledResultSet};
+use crate::sql::{replace_placeholders, SqlValue};
+use crate::tags::{TagError, Tags, MAX_TAGS};
+use crate::validation::{ValidationReport, Validators};
+use crate::watch::WatchedRegistry;
+use futures::future;
+use futures::stream::{self, Stream, StreamExt, TryStreamExt};
+use jsonrpsee::core::client::ClientT;
+use jsonrpsee::core::params::BatchRequestBuilder;
 pub use jsonrpsee::core::ClientError;
 use jsonrpsee::http_client::{HeaderMap, HttpClient, HttpClientBuilder};
+use jsonrpsee::rpc_params;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::collections::HashMap;
+use std::future::Future;
+use std::pin::pin;
+use std::sync::{Arc, RwLock};
 use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
+pub use tokio_util::sync::CancellationToken;
 
 #[derive(Debug, Clone, Default)]
 pub struct Query {
     /// SQL query to execute
     pub sql: String,
-    /// the maximum age of the query results in minutes you will accept, defaults to zero
-    pub max_age_minutes: Option<u64>,
-    /// An override on the cache. A value of true will reexecute the query.
-    pub cached: Option<bool>,
+    /// Whether and how long cached results may be reused
+    pub cache_policy: CachePolicy,
+    /// How long the results are kept, derived from the client's `TtlPolicy` if unset
+    pub result_ttl: Option<ResultTtl>,
     /// The number of minutes until your query times out
     pub timeout: Option<Duration>,
     /// The number of seconds to use between retries
     pub retry_interval_seconds: Option<Duration>,
+    /// How polls are spaced out, overrides the client's strategy and
+    /// `retry_interval_seconds`
+    pub poll_strategy: Option<Arc<dyn PollStrategy>>,
     /// The data source to execute the query against
-    pub data_source: Option<String>,
+    pub data_source: Option<DataSource>,
     /// The owner of the data source
-    pub data_provider: Option<String>,
+    pub data_provider: Option<DataProvider>,
+    /// Columns the results must have, checked once the run succeeds
+    pub expected_schema: Option<Schema>,
+    /// Tags attached to the run
+    pub tags: Tags,
+    /// Whether to cancel the run if it times out, overrides the client's setting
+    pub cancel_on_timeout: Option<bool>,
+    /// How long the statement may execute server-side, sent in whole seconds.
+    /// Unlike `timeout`, this is enforced by the data source.
+    pub statement_timeout: Option<Duration>,
 }
 
 impl Query {
-    pub fn new(sql: String) -> Self {
+    pub fn new(sql: impl Into<String>) -> Self {
         Self {
-            sql,
+            sql: sql.into(),
             ..Default::default()
         }
     }
+
+    /// Accept cached results up to `minutes` old
+    pub fn max_age_minutes(mut self, minutes: u64) -> Self {
+        self.cache_policy = CachePolicy::UseCacheUpTo(MaxAge::from_minutes(minutes).0);
+        self
+    }
+
+    /// `false` always re-executes the query, `true` restores the server's default
+    /// max age unless one was set
+    pub fn cached(mut self, cached: bool) -> Self {
+        self.cache_policy = match (cached, self.cache_policy) {
+            (false, _) => CachePolicy::BypassCache,
+            (true, CachePolicy::BypassCache) => CachePolicy::ServerDefault,
+            (true, cache_policy) => cache_policy,
+        };
+        self
+    }
+
+    pub fn result_ttl(mut self, result_ttl: ResultTtl) -> Self {
+        self.result_ttl = Some(result_ttl);
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn retry_interval(mut self, retry_interval: Duration) -> Self {
+        self.retry_interval_seconds = Some(retry_interval);
+        self
+    }
+
+    pub fn poll_strategy(mut self, poll_strategy: impl PollStrategy + 'static) -> Self {
+        self.poll_strategy = Some(Arc::new(poll_strategy));
+        self
+    }
+
+    pub fn data_source(mut self, data_source: impl Into<DataSource>) -> Self {
+        self.data_source = Some(data_source.into());
+        self
+    }
+
+    pub fn data_provider(mut self, data_provider: impl Into<DataProvider>) -> Self {
+        self.data_provider = Some(data_provider.into());
+        self
+    }
+
+    pub fn cancel_on_timeout(mut self, cancel_on_timeout: bool) -> Self {
+        self.cancel_on_timeout = Some(cancel_on_timeout);
+        self
+    }
+
+    pub fn expected_schema(mut self, expected_schema: Schema) -> Self {
+        self.expected_schema = Some(expected_schema);
+        self
+    }
+
+    pub fn statement_timeout(mut self, statement_timeout: Duration) -> Self {
+        self.statement_timeout = Some(statement_timeout);
+        self
+    }
+
+    /// Replaces the `:name` placeholders of the SQL with `value`, rendered as a SQL
+    /// literal
+    pub fn bind(mut self, name: &str, value: impl Into<SqlValue>) -> Self {
+        let literal = value.into().to_sql();
+        self.sql = replace_placeholders(&self.sql, |placeholder| {
+            (placeholder == name).then(|| literal.clone())
+        });
+        self
+    }
+
+    /// Replaces the run's tags
+    pub fn tags(mut self, tags: Tags) -> Self {
+        self.tags = tags;
+        self
+    }
+
+    /// Adds a tag to the run, e.g. to attribute its cost or find it later
+    pub fn tag(
+        mut self,
+        key: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Result<Self, TagError> {
+        self.tags.insert(key, value)?;
+        Ok(self)
+    }
 }
 
-#[derive(Debug, Clone)]
+/// Data source a query is executed against. Unknown names parse to `Custom`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum DataSource {
+    /// `snowflake-default`
+    SnowflakeDefault,
+    Custom(String),
+}
+
+impl DataSource {
+    pub fn as_str(&self) -> &str {
+        match self {
+            DataSource::SnowflakeDefault => DATA_SOURCE,
+            DataSource::Custom(name) => name,
+        }
+    }
+}
+
+impl Default for DataSource {
+    fn default() -> Self {
+        DataSource::from(DATA_SOURCE)
+    }
+}
+
+impl From<&str> for DataSource {
+    fn from(name: &str) -> Self {
+        match name {
+            DATA_SOURCE => DataSource::SnowflakeDefault,
+            name => DataSource::Custom(name.to_string()),
+        }
+    }
+}
+
+impl From<String> for DataSource {
+    fn from(name: String) -> Self {
+        DataSource::from(name.as_str())
+    }
+}
+
+impl From<DataSource> for String {
+    fn from(data_source: DataSource) -> Self {
+        data_source.as_str().to_string()
+    }
+}
+
+/// Owner of a data source. Unknown names parse to `Custom`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum DataProvider {
+    /// `flipside`
+    Flipside,
+    Custom(String),
+}
+
+impl DataProvider {
+    pub fn as_str(&self) -> &str {
+        match self {
+            DataProvider::Flipside => DATA_PROVIDER,
+            DataProvider::Custom(name) => name,
+        }
+    }
+}
+
+impl Default for DataProvider {
+    fn default() -> Self {
+        DataProvider::from(DATA_PROVIDER)
+    }
+}
+
+impl From<&str> for DataProvider {
+    fn from(name: &str) -> Self {
+        match name {
+            DATA_PROVIDER => DataProvider::Flipside,
+            name => DataProvider::Custom(name.to_string()),
+        }
+    }
+}
+
+impl From<String> for DataProvider {
+    fn from(name: String) -> Self {
+        DataProvider::from(name.as_str())
+    }
+}
+
+impl From<DataProvider> for String {
+    fn from(data_provider: DataProvider) -> Self {
+        data_provider.as_str().to_string()
+    }
+}
+
+/// How old cached results may be, sent to the API in whole minutes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct MaxAge(pub Duration);
+
+impl MaxAge {
+    pub const fn from_minutes(minutes: u64) -> Self {
+        Self(Duration::from_secs(minutes * 60))
+    }
+
+    /// Wire value, rounded down
+    pub fn minutes(&self) -> u64 {
+        self.0.as_secs() / 60
+    }
+}
+
+/// How long results are kept, sent to the API in whole hours
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct ResultTtl(pub Duration);
+
+impl ResultTtl {
+    pub const fn from_hours(hours: u64) -> Self {
+        Self(Duration::from_secs(hours * 60 * 60))
+    }
+
+    /// Wire value, rounded up so results never expire early
+    pub fn hours(&self) -> u64 {
+        self.0.as_secs().div_ceil(60 * 60)
+    }
+}
+
+/// Controls reuse of results from previous runs of the same query
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CachePolicy {
+    /// Accept cached results up to this old
+    UseCacheUpTo(Duration),
+    /// Always re-execute the query
+    BypassCache,
+    /// Use `MAX_AGE_MINUTES`
+    ServerDefault,
+}
+
+impl CachePolicy {
+    pub fn max_age(&self) -> MaxAge {
+        match self {
+            CachePolicy::UseCacheUpTo(max_age) => MaxAge(*max_age),
+            CachePolicy::BypassCache => MaxAge::default(),
+            CachePolicy::ServerDefault => MaxAge::from_minutes(MAX_AGE_MINUTES),
+        }
+    }
+}
+
+impl Default for CachePolicy {
+    fn default() -> Self {
+        if CACHED {
+            CachePolicy::ServerDefault
+        } else {
+            CachePolicy::BypassCache
+        }
+    }
+}
+
+/// Derives the result TTL for queries that don't set it
+#[derive(Clone, Default)]
+pub enum TtlPolicy {
+    /// The larger of the max age and `TTL_MINUTES`
+    #[default]
+    FromMaxAge,
+    Fixed(ResultTtl),
+    Custom(Arc<dyn Fn(MaxAge) -> ResultTtl + Send + Sync>),
+}
+
+impl TtlPolicy {
+    pub fn result_ttl(&self, max_age: MaxAge) -> ResultTtl {
+        match self {
+            TtlPolicy::FromMaxAge => {
+                ResultTtl(max_age.0.max(Duration::from_secs(TTL_MINUTES * 60)))
+            }
+            TtlPolicy::Fixed(ttl) => *ttl,
+            TtlPolicy::Custom(f) => f(max_age),
+        }
+    }
+}
+
+/// Error details of a failed run. The API may omit any of them, notably for
+/// cancelled runs.
+#[derive(Debug, Clone, Default, thiserror::Error)]
+#[error(
+    "{}: {}",
+    name.as_deref().unwrap_or("QueryRunFailed"),
+    message.as_deref().unwrap_or("no error details")
+)]
 pub struct ExecutionError {
-    pub name: String,
-    pub message: String,
-    pub data: String,
+    pub name: Option<String>,
+    pub message: Option<String>,
+    pub data: Option<String>,
 }
 
-#[derive(Debug)]
+impl From<&QueryRun> for ExecutionError {
+    fn from(query_run: &QueryRun) -> Self {
+        ExecutionError {
+            name: query_run.error_name.clone(),
+            message: query_run.error_message.clone(),
+            data: query_run.error_data.clone(),
+        }
+    }
+}
+
+/// Broad cause of a failed run, as reported by the data source
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCategory {
+    OutOfMemory,
+    Timeout,
+    Permission,
+    Syntax,
+    Other,
+}
+
+impl ExecutionError {
+    /// `data` parsed as JSON, `None` if it's missing or isn't JSON
+    pub fn data_json(&self) -> Option<serde_json::Value> {
+        serde_json::from_str(self.data.as_deref()?).ok()
+    }
+
+    /// Classifies the error from the messages Snowflake uses for common failures
+    pub fn category(&self) -> ErrorCategory {
+        let text = [&self.name, &self.message, &self.data]
+            .into_iter()
+            .flatten()
+            .map(|part| part.to_lowercase())
+            .collect::<Vec<_>>()
+            .join(" ");
+        let matches = |patterns: &[&str]| patterns.iter().any(|pattern| text.contains(pattern));
+
+        if matches(&["out of memory", "memory limit", "insufficient memory"]) {
+            ErrorCategory::OutOfMemory
+        } else if matches(&["timeout", "timed out", "time limit"]) {
+            ErrorCategory::Timeout
+        } else if matches(&[
+            "not authorized",
+            "insufficient privileges",
+            "permission denied",
+            "access denied",
+        ]) {
+            ErrorCategory::Permission
+        } else if matches(&[
+            "syntax error",
+            "sql compilation error",
+            "invalid identifier",
+            "unexpected '",
+        ]) {
+            ErrorCategory::Syntax
+        } else {
+            ErrorCategory::Other
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
 pub enum QueryRunError {
-    RpcError(ClientError),
-    Timeout(Duration),
-    ExecutionError(ExecutionError),
+    #[error(transparent)]
+    RpcError(#[from] ApiError),
+    /// The run was still going when the client gave up on it
+    #[error("query run {} timed out after {elapsed:?}", query_run.id)]
+    Timeout {
+        query_run: Box<QueryRun>,
+        elapsed: Duration,
+    },
+    #[error("query run failed: {0}")]
+    ExecutionError(#[source] ExecutionError),
+    /// The run was cancelled server-side, by a user or by the system
+    #[error("query run {} was cancelled", .0.id)]
+    Cancelled(Box<QueryRun>),
+    #[error("results don't match the expected schema: {0:?}")]
+    SchemaMismatch(SchemaDiff),
+    /// Waiting was cancelled by the caller. The run is only cancelled server-side
+    /// if that was requested.
+    #[error("stopped waiting for query run {}", query_run.id)]
+    Aborted { query_run: Box<QueryRun> },
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum FetchError {
+    #[error(transparent)]
+    RpcError(#[from] ApiError),
+    #[error("failed to spill results to disk")]
+    IoError(#[source] std::io::Error),
+    #[error("failed to deserialize a row")]
+    DeserializeError(#[source] serde_json::Error),
+}
+
+#[derive(Debug)]
+pub enum FetchedResults {
+    InMemory(ResultSet),
+    Spilled(SpilledResultSet),
+}
+
+#[derive(Debug, Clone)]
+pub struct CachedResults {
+    pub query_run: QueryRun,
+    pub result_set: ResultSet,
+}
+
+/// A completed run along with a page of its results
+#[derive(Debug, Clone)]
+pub struct QueryResultSet {
+    pub query_run: QueryRun,
+    pub result_set: ResultSet,
+    pub page: PaginationDetails,
+}
+
+#[derive(Debug, Clone)]
+pub struct RefetchedResults {
+    pub results: CachedResults,
+    /// The run hadn't changed, so `results` is the cached copy
+    pub not_modified: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct ResubmittedResults {
+    pub results: GetQueryRunResultsResult,
+    /// The original run was archived or its results had expired, so its SQL was
+    /// executed again and `results` come from the new run
+    pub resubmitted: bool,
 }
 
 #[derive(Clone)]
-pub struct Flipside(HttpClient);
+pub struct Flipside {
+    client: HttpClient,
+    memory_budget: Option<u64>,
+    pub(crate) runtimes: RuntimeHistory,
+    dedup_window: Option<Duration>,
+    recent_runs: RecentRuns,
+    ttl_policy: TtlPolicy,
+    result_format: QueryFormat,
+    max_rows: Option<usize>,
+    cancel_on_timeout: bool,
+    poll_strategy: Option<Arc<dyn PollStrategy>>,
+    retry_policy: RetryPolicy,
+    rate_limiter: RateLimiter,
+    run_slots: Option<Arc<Semaphore>>,
+    pub(crate) shared_poller: Option<SharedPoller>,
+    sdk_tags: Tags,
+    library: Option<Arc<RwLock<Arc<QueryRegistry>>>>,
+}
 
 impl Flipside {
-    pub fn new(api_key: String, base_url: Option<String>) -> Result<Self, ClientError> {
+    pub fn new(api_key: String, base_url: Option<String>) -> Result<Self, ApiError> {
         let mut headers = HeaderMap::new();
         headers.insert("x-api-key", api_key.parse().unwrap());
 
-        Ok(Self(
-            HttpClientBuilder::default()
+        Ok(Self {
+            client: HttpClientBuilder::default()
                 .set_headers(headers)
                 .build(base_url.unwrap_or(API_BASE_URL.to_string()))?,
-        ))
+            memory_budget: None,
+            runtimes: RuntimeHistory::default(),
+            dedup_window: None,
+            recent_runs: RecentRuns::default(),
+            ttl_policy: TtlPolicy::default(),
+            result_format: RESULT_FORMAT,
+            max_rows: None,
+            cancel_on_timeout: false,
+            poll_strategy: None,
+            retry_policy: RetryPolicy::default(),
+            rate_limiter: RateLimiter::default(),
+            run_slots: None,
+            shared_poller: None,
+            sdk_tags: Tags::new()
+                .with("sdk_package", SDK_PACKAGE)
+                .and_then(|tags| tags.with("sdk_version", SDK_VERSION))
+                .expect("SDK tags are valid"),
+            library: None,
+        })
+    }
+
+    /// Retries of transient RPC failures when polling runs and fetching their
+    /// results. Creating a run is only retried when rate limited.
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Limits API calls made through this client and its clones to
+    /// `requests_per_second`, waiting before calls that would exceed it. Fails if
+    /// the rate isn't finite or is below one request a day.
+    pub fn with_requests_per_second(
+        mut self,
+        requests_per_second: f64,
+    ) -> Result<Self, InvalidRate> {
+        self.rate_limiter
+            .set_requests_per_second(requests_per_second)?;
+        Ok(self)
+    }
+
+    /// Limits query runs created through this client and its clones to
+    /// `queries_per_minute`, waiting before submissions that would exceed it.
+    /// Fails if the rate is zero.
+    pub fn with_queries_per_minute(mut self, queries_per_minute: u32) -> Result<Self, InvalidRate> {
+        self.rate_limiter
+            .set_queries_per_minute(queries_per_minute)?;
+        Ok(self)
+    }
+
+    /// Runs submitted through this client and its clones that may be in flight at
+    /// once. Further submissions wait until one of them finishes or its handle is
+    /// dropped.
+    pub fn with_max_concurrent_runs(mut self, max_concurrent_runs: usize) -> Self {
+        self.run_slots = Some(Arc::new(Semaphore::new(max_concurrent_runs)));
+        self
+    }
+
+    /// Tags every run created through this client with `app_name`, next to the
+    /// `sdk_package` and `sdk_version` tags identifying the SDK
+    pub fn with_app_name(mut self, app_name: impl Into<String>) -> Result<Self, TagError> {
+        self.sdk_tags.insert("app_name", app_name)?;
+        Ok(self)
+    }
+
+    /// Registry of named queries run by `run_named`
+    pub fn with_query_library(mut self, library: QueryRegistry) -> Self {
+        self.library = Some(Arc::new(RwLock::new(Arc::new(library))));
+        self
+    }
+
+    /// Like `with_query_library`, following the reloads of `library`
+    pub fn with_watched_library(mut self, library: &WatchedRegistry) -> Self {
+        self.library = Some(library.registry.clone());
+        self
+    }
+
+    /// Polls every run being waited on through this client and its clones with a
+    /// single batch request every `interval`, instead of one request per run on
+    /// each run's own poll strategy
+    pub fn with_shared_poller(mut self, interval: Duration) -> Self {
+        self.shared_poller = Some(SharedPoller::new(interval));
+        self
+    }
+
+    /// How polls are spaced out for queries that don't set their own strategy or
+    /// retry interval. Defaults to `Linear(RETRY_INTERVAL)` capped at
+    /// `MAX_POLL_INTERVAL`.
+    pub fn with_poll_strategy(mut self, poll_strategy: impl PollStrategy + 'static) -> Self {
+        self.poll_strategy = Some(Arc::new(poll_strategy));
+        self
+    }
+
+    /// Cancels runs that exceed their timeout instead of leaving them running
+    /// server-side
+    pub fn with_cancel_on_timeout(mut self, cancel_on_timeout: bool) -> Self {
+        self.cancel_on_timeout = cancel_on_timeout;
+        self
+    }
+
+    /// Stops fetching results once `max_rows` rows have been read, truncating the
+    /// last page, wherever results are paginated
+    pub fn with_max_rows(mut self, max_rows: usize) -> Self {
+        self.max_rows = Some(max_rows);
+        self
+    }
+
+    /// Format results are requested in, JSON or CSV. Rows are normalized either
+    /// way, but CSV is smaller on the wire.
+    pub fn with_result_format(mut self, result_format: QueryFormat) -> Self {
+        self.result_format = result_format;
+        self
+    }
+
+    pub fn with_ttl_policy(mut self, ttl_policy: TtlPolicy) -> Self {
+        self.ttl_policy = ttl_policy;
+        self
+    }
+
+    /// Identical submissions (same SQL, data source, tags, cache policy, TTL and
+    /// statement timeout) within `window` of a run created by this client, or while
+    /// it is being created, attach to that run instead of creating a new one.
+    /// Queries that bypass the cache always create a new run.
+    ///
+    /// Only runs this client saw being created are known: the API has no method
+    /// listing runs, so a run whose creation response was lost can't be looked up
+    /// and attached to. That's why such a creation isn't resent either, see
+    /// `create_query_run`.
+    pub fn with_dedup_window(mut self, window: Duration) -> Self {
+        self.dedup_window = Some(window);
+        self
+    }
+
+    /// Maximum number of bytes of results to hold in memory before `fetch_results`
+    /// spills to disk instead
+    pub fn with_memory_budget(mut self, bytes: u64) -> Self {
+        self.memory_budget = Some(bytes);
+        self
     }
 
     pub async fn run(&self, query: Query) -> Result<QueryRun, QueryRunError> {
-        let retry_interval = query.retry_interval_seconds.unwrap_or(RETRY_INTERVAL);
-        let timeout = query.timeout.unwrap_or(TIMEOUT);
+        let expected_schema = query.expected_schema.clone();
 
-        let mut query_run = self
-            .create_query_run(query)
+        let query_run = self
+            .submit(query)
             .await
-            .map_err(QueryRunError::RpcError)?;
+            .map_err(QueryRunError::RpcError)?
+            .wait()
+            .await?;
+
+        self.check_schema(expected_schema, query_run).await
+    }
 
-        let query_run_id = query_run.id;
+    /// Like `run`, but stops waiting once `token` is cancelled, cancelling the run
+    /// server-side too if `cancel_remote` is set
+    pub async fn run_with_cancellation(
+        &self,
+        query: Query,
+        token: &CancellationToken,
+        cancel_remote: bool,
+    ) -> Result<QueryRun, QueryRunError> {
+        let expected_schema = query.expected_schema.clone();
+
+        let query_run = self
+            .submit(query)
+            .await
+            .map_err(QueryRunError::RpcError)?
+            .wait_with_cancellation(token, cancel_remote)
+            .await?;
 
-        let mut retry_duration = retry_interval;
-        let start = Instant::now();
+        self.check_schema(expected_schema, query_run).await
+    }
 
-        loop {
-            let res = self
-                .0
-                .get_query_run(QueryRunIdParams {
-                    query_run_id: query_run_id.clone(),
-                })
+    async fn check_schema(
+        &self,
+        expected_schema: Option<Schema>,
+        query_run: QueryRun,
+    ) -> Result<QueryRun, QueryRunError> {
+        if let Some(expected_schema) = expected_schema {
+            let schema = self
+                .get_schema(query_run.sql_statement_id.clone())
                 .await
                 .map_err(QueryRunError::RpcError)?;
+            expected_schema
+                .validate(&schema)
+                .map_err(QueryRunError::SchemaMismatch)?;
+        }
+
+        Ok(query_run)
+    }
 
-            query_run = res.redirected_to_query_run.unwrap_or(res.query_run);
+    /// Runs a query of the client's library by name with typed parameters and
+    /// fetches all of its results, see `QueryRegistry::run_named`
+    pub async fn run_named(
+        &self,
+        name: &str,
+        params: &HashMap<String, Value>,
+    ) -> Result<ResultSet, RegistryError> {
+        let library = match &self.library {
+            Some(library) => library.read().unwrap().clone(),
+            None => return Err(RegistryError::UnknownQuery(name.to_string())),
+        };
+        library.run_named(self, name, params).await
+    }
 
-            match query_run.state {
-                QueryState::QueryStateSuccess => break,
+    /// Runs every query with at most `max_parallel` in flight at once, returning
+    /// their outcomes in the order of `queries`. A failed query doesn't stop the
+    /// others.
+    pub async fn run_many(
+        &self,
+        queries: Vec<Query>,
+        max_parallel: usize,
+    ) -> Vec<Result<QueryRun, QueryRunError>> {
+        stream::iter(queries)
+            .map(|query| self.run(query))
+            .buffered(max_parallel.max(1))
+            .collect()
+            .await
+    }
 
-                QueryState::QueryStateFailed | QueryState::QueryStateCancelled => {
-                    return Err(QueryRunError::ExecutionError(ExecutionError {
-                        name: query_run.error_name.unwrap(),
-                        message: query_run.error_message.unwrap(),
-                        data: query_run.error_data.unwrap(),
-                    }));
-                }
+    /// Like `run_many`, running queries again after transient failures according
+    /// to `retry_policy`, so one failed query doesn't force re-running the set
+    pub async fn run_many_with_retry(
+        &self,
+        queries: Vec<Query>,
+        max_parallel: usize,
+        retry_policy: RunRetryPolicy,
+    ) -> Vec<Result<QueryRun, QueryRunError>> {
+        stream::iter(queries)
+            .map(|query| retry_policy.run(self, query))
+            .buffered(max_parallel.max(1))
+            .collect()
+            .await
+    }
 
-                _ => {}
-            };
+    /// Runs the query and fetches the first page of its results
+    pub async fn run_and_get_results(&self, query: Query) -> Result<QueryResultSet, QueryRunError> {
+        let query_run = self.run(query).await?;
+        let results = self
+            .get_query_results(query_run.id.clone(), None, Vec::new(), Vec::new())
+            .await
+            .map_err(QueryRunError::RpcError)?;
+        let page = results.page.clone();
 
-            tokio::time::sleep(retry_duration).await;
-            retry_duration += retry_interval;
+        Ok(QueryResultSet {
+            query_run,
+            result_set: results.into(),
+            page,
+        })
+    }
 
-            let elapsed = start.elapsed();
-            if elapsed > timeout {
-                return Err(QueryRunError::Timeout(elapsed));
+    /// Creates a query run without waiting for it to complete
+    pub async fn submit(&self, query: Query) -> Result<QueryRunHandle, ApiError> {
+        let poll_strategy = match (&query.poll_strategy, query.retry_interval_seconds) {
+            (Some(poll_strategy), _) => poll_strategy.clone(),
+            (None, Some(retry_interval)) => {
+                Arc::new(Linear(retry_interval).capped(MAX_POLL_INTERVAL))
             }
-        }
+            (None, None) => self.default_poll_strategy(),
+        };
+        let timeout = query.timeout.unwrap_or(TIMEOUT);
+        let cancel_on_timeout = query.cancel_on_timeout.unwrap_or(self.cancel_on_timeout);
+        let run_slot = match &self.run_slots {
+            Some(run_slots) => Some(
+                run_slots
+                    .clone()
+                    .acquire_owned()
+                    .await
+                    .expect("run slots are never closed"),
+            ),
+            None => None,
+        };
+        let query_run = self.create_query_run(query).await?;
+        let run_slot = run_slot.filter(|_| !query_run.state.is_terminal());
 
-        Ok(query_run)
+        Ok(QueryRunHandle {
+            flipside: self.clone(),
+            query_run_id: query_run.id.clone(),
+            query_run,
+            submitted_at: Instant::now(),
+            poll_strategy,
+            timeout,
+            cancel_on_timeout,
+            cancel_on_drop: false,
+            on_state_change: None,
+            state_tx: None,
+            run_slot,
+            shared_updates: None,
+        })
     }
 
-    pub async fn create_query_run(&self, query: Query) -> Result<QueryRun, ClientError> {
-        let max_age_minutes = if query.cached == Some(false) {
-            0
-        } else {
-            query.max_age_minutes.unwrap_or(MAX_AGE_MINUTES)
+    /// Handle to a run created earlier, e.g. by a previous process, using the
+    /// default timeout and retry interval
+    pub async fn attach(&self, query_run_id: String) -> Result<QueryRunHandle, ApiError> {
+        let query_run = self.get_query_run(query_run_id).await?;
+
+        Ok(QueryRunHandle {
+            flipside: self.clone(),
+            query_run_id: query_run.id.clone(),
+            query_run,
+            submitted_at: Instant::now(),
+            poll_strategy: self.default_poll_strategy(),
+            timeout: TIMEOUT,
+            cancel_on_timeout: self.cancel_on_timeout,
+            cancel_on_drop: false,
+            on_state_change: None,
+            state_tx: None,
+            run_slot: None,
+            shared_updates: None,
+        })
+    }
+
+    /// Polls an existing run until it succeeds, fails or times out, like `run`
+    pub async fn wait_for_completion(
+        &self,
+        query_run_id: String,
+    ) -> Result<QueryRun, QueryRunError> {
+        self.attach(query_run_id)
+            .await
+            .map_err(QueryRunError::RpcError)?
+            .wait()
+            .await
+    }
+
+    fn default_poll_strategy(&self) -> Arc<dyn PollStrategy> {
+        self.poll_strategy
+            .clone()
+            .unwrap_or_else(|| Arc::new(Linear(RETRY_INTERVAL).capped(MAX_POLL_INTERVAL)))
+    }
+
+    /// Creates a run, or attaches to a recent identical one if a dedup window is
+    /// set. Only rate limited attempts are retried: after a network failure the run
+    /// may exist on the server, so the error is returned rather than risking a
+    /// duplicate.
+    pub async fn create_query_run(&self, query: Query) -> Result<QueryRun, ApiError> {
+        let max_age = query.cache_policy.max_age();
+        let mut tags = query.tags;
+        for (key, value) in self.sdk_tags.iter() {
+            // The query's own tags take precedence if there's no room left
+            if let (false, true, Some(value)) =
+                (tags.contains_key(key), tags.len() < MAX_TAGS, value)
+            {
+                // SDK tags were validated when added to `sdk_tags`, and there's room
+                tags.insert(key, value).expect("SDK tags are valid");
+            }
+        }
+
+        let params = CreateQueryRunParams {
+            result_ttl_hours: query
+                .result_ttl
+                .unwrap_or_else(|| self.ttl_policy.result_ttl(max_age))
+                .hours(),
+            max_age_minutes: max_age.minutes(),
+            sql: query.sql,
+            tags,
+            data_source: query.data_source.unwrap_or_default().into(),
+            data_provider: query.data_provider.unwrap_or_default().into(),
+            statement_timeout_seconds: query
+                .statement_timeout
+                .map(|statement_timeout| statement_timeout.as_secs()),
         };
 
-        Ok(self
-            .0
-            .create_query_run(CreateQueryRunParams {
-                result_ttl_hours: max_age_minutes.max(TTL_MINUTES) / 60,
-                max_age_minutes,
-                sql: query.sql,
-                tags: HashMap::with_capacity(0),
-                data_source: query.data_source.unwrap_or(DATA_SOURCE.to_string()),
-                data_provider: query.data_provider.unwrap_or(DATA_PROVIDER.to_string()),
+        let window = match (self.dedup_window, query.cache_policy) {
+            (Some(window), policy) if policy != CachePolicy::BypassCache => window,
+            _ => return self.send_create_query_run(params).await,
+        };
+
+        let key = RecentRuns::key(&params);
+        let mut created = None;
+        let (query_run_id, _) = self
+            .recent_runs
+            .entry(key, window)
+            .get_or_try_init(|| async {
+                let query_run = self.send_create_query_run(params).await?;
+                let entry = (query_run.id.clone(), Instant::now());
+                created = Some(query_run);
+                Ok::<_, ApiError>(entry)
             })
             .await?
+            .clone();
+
+        match created {
+            Some(query_run) => Ok(query_run),
+            None => self.get_query_run(query_run_id).await,
+        }
+    }
+
+    /// Creating a run isn't idempotent, so only rate limited attempts are retried:
+    /// after a network failure the run may exist on the server, and without a way
+    /// to list runs it can't be found to attach to
+    async fn send_create_query_run(
+        &self,
+        params: CreateQueryRunParams,
+    ) -> Result<QueryRun, ApiError> {
+        self.rate_limiter.acquire_query().await;
+        Ok(self
+            .retry_policy
+            .retry_rate_limited(|| self.throttled(self.client.create_query_run(params.clone())))
+            .await?
             .query_run)
     }
 
-    pub async fn get_query_run(&self, query_run_id: String) -> Result<QueryRun, ClientError> {
+    /// Waits for the client's rate limit before sending `request`
+    async fn throttled<T>(
+        &self,
+        request: impl Future<Output = Result<T, ClientError>>,
+    ) -> Result<T, ClientError> {
+        self.rate_limiter.acquire_request().await;
+        request.await
+    }
+
+    pub async fn get_query_run(&self, query_run_id: String) -> Result<QueryRun, ApiError> {
+        let params = QueryRunIdParams { query_run_id };
         let res = self
-            .0
-            .get_query_run(QueryRunIdParams { query_run_id })
+            .retry_policy
+            .retry(|| self.throttled(self.client.get_query_run(params.clone())))
             .await?;
         Ok(res.redirected_to_query_run.unwrap_or(res.query_run))
     }
 
-    pub async fn cancel_query_run(&self, query_run_id: String) -> Result<QueryRun, ClientError> {
+    /// Fetches several runs with a single JSON-RPC batch request. The outer error
+    /// is a failure of the whole batch, the inner ones of individual runs, in the
+    /// order of `query_run_ids`.
+    pub async fn get_query_runs(
+        &self,
+        query_run_ids: Vec<String>,
+    ) -> Result<Vec<Result<QueryRun, ApiError>>, ApiError> {
+        if query_run_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut batch = BatchRequestBuilder::new();
+        for query_run_id in query_run_ids {
+            batch
+                .insert(
+                    "getQueryRun",
+                    rpc_params![QueryRunIdParams { query_run_id }],
+                )
+                .map_err(ClientError::ParseError)?;
+        }
+
+        let res = self
+            .retry_policy
+            .retry(|| {
+                self.throttled(
+                    self.client
+                        .batch_request::<GetQueryRunResult>(batch.clone()),
+                )
+            })
+            .await?;
+        Ok(res
+            .into_iter()
+            .map(|entry| match entry {
+                Ok(res) => Ok(res.redirected_to_query_run.unwrap_or(res.query_run)),
+                Err(err) => Err(ClientError::Call(err.into_owned()).into()),
+            })
+            .collect())
+    }
+
+    /// Refreshes every handle with one batch request instead of one request per
+    /// run. The outer error is a failure of the whole batch, the inner ones of
+    /// individual runs, in the order of `handles`.
+    pub async fn poll_all(
+        &self,
+        handles: &mut [QueryRunHandle],
+    ) -> Result<Vec<Result<(), ApiError>>, ApiError> {
+        let query_run_ids = handles
+            .iter()
+            .map(|handle| handle.id().to_string())
+            .collect();
+        let query_runs = self.get_query_runs(query_run_ids).await?;
+
+        Ok(handles
+            .iter_mut()
+            .zip(query_runs)
+            .map(|(handle, query_run)| query_run.map(|query_run| handle.update(query_run)))
+            .collect())
+    }
+
+    pub async fn get_sql_statement(
+        &self,
+        sql_statement_id: String,
+    ) -> Result<SqlStatement, ApiError> {
         Ok(self
-            .0
-            .cancel_query_run(QueryRunIdParams { query_run_id })
+            .throttled(
+                self.client
+                    .get_sql_statement(SqlStatementIdParams { sql_statement_id }),
+            )
+            .await?
+            .sql_statement)
+    }
+
+    /// Result columns of a statement, empty if it hasn't been executed yet
+    pub async fn get_schema(&self, sql_statement_id: String) -> Result<Schema, ApiError> {
+        Ok(self
+            .get_sql_statement(sql_statement_id)
+            .await?
+            .column_metadata
+            .map(|metadata| Schema::from_metadata(&metadata))
+            .unwrap_or_default())
+    }
+
+    pub async fn cancel_query_run(&self, query_run_id: String) -> Result<QueryRun, ApiError> {
+        Ok(self
+            .throttled(
+                self.client
+                    .cancel_query_run(QueryRunIdParams { query_run_id }),
+            )
             .await?
             .canceled_query_run)
     }
@@ -158,27 +1028,569 @@ impl Flipside {
         &self,
         query_run_id: String,
         page: Option<Pagination>,
-        filters: Vec<HashMap<FilterKey, String>>,
+        filters: Vec<Filter>,
         sort_by: Vec<SortBy>,
-    ) -> Result<GetQueryRunResultsResult, ClientError> {
-        let res = self
-            .0
-            .get_query_run(QueryRunIdParams { query_run_id })
+    ) -> Result<GetQueryRunResultsResult, ApiError> {
+        let query_run = self.get_query_run(query_run_id).await?;
+        let params = GetQueryRunResultsParams {
+            query_run_id: query_run.id,
+            format: self.result_format,
+            sort_by,
+            filters,
+            page: Some(page.unwrap_or(Pagination {
+                number: 1,
+                size: 100000,
+            })),
+        };
+
+        retry_not_ready(|| {
+            self.retry_policy
+                .retry(|| self.throttled(self.client.get_query_run_results(params.clone())))
+        })
+        .await
+    }
+
+    /// Fetches every page of results, stopping once `max_rows` rows have been read
+    pub async fn get_all_query_results(
+        &self,
+        query_run_id: String,
+        max_rows: Option<usize>,
+    ) -> Result<ResultSet, ApiError> {
+        let query_run = self.get_query_run(query_run_id).await?;
+        self.collect_results(&query_run.id, max_rows).await
+    }
+
+    /// Total number of result rows, from the run's metadata when available and
+    /// otherwise from a single-row page
+    pub async fn get_row_count(&self, query_run_id: String) -> Result<usize, ApiError> {
+        let query_run = self.get_query_run(query_run_id).await?;
+        match query_run.row_count {
+            Some(row_count) => Ok(row_count),
+            None => Ok(self
+                .get_results_page(&query_run.id, PAGE_NUMBER, 1)
+                .await?
+                .page
+                .total_rows),
+        }
+    }
+
+    /// First `n` rows of a run's results
+    pub async fn preview(&self, query_run_id: String, n: usize) -> Result<ResultSet, ApiError> {
+        let query_run = self.get_query_run(query_run_id).await?;
+        let mut result_set = ResultSet::from(
+            self.get_results_page(&query_run.id, PAGE_NUMBER, n.max(1))
+                .await?,
+        );
+        result_set.rows.truncate(n);
+        Ok(result_set)
+    }
+
+    /// Result columns of a run, read from a single-row page
+    pub async fn get_columns(&self, query_run_id: String) -> Result<Schema, ApiError> {
+        let query_run = self.get_query_run(query_run_id).await?;
+        let page = self.get_results_page(&query_run.id, PAGE_NUMBER, 1).await?;
+
+        Ok(Schema {
+            columns: page
+                .column_names
+                .into_iter()
+                .zip(page.column_types)
+                .collect(),
+        })
+    }
+
+    /// Like `get_query_results`, with each row deserialized into `T` by column name
+    pub async fn get_query_results_as<T: DeserializeOwned>(
+        &self,
+        query_run_id: String,
+        page: Option<Pagination>,
+        filters: Vec<Filter>,
+        sort_by: Vec<SortBy>,
+    ) -> Result<Vec<T>, FetchError> {
+        let results = self
+            .get_query_results(query_run_id, page, filters, sort_by)
+            .await
+            .map_err(FetchError::RpcError)?;
+
+        ResultSet::from(results)
+            .rows_as()
+            .map_err(FetchError::DeserializeError)
+    }
+
+    /// Like `get_query_results`, but if the run has been archived or its results
+    /// have expired, its SQL statement is executed again and the new run's results
+    /// are returned instead
+    pub async fn get_query_results_or_resubmit(
+        &self,
+        query_run_id: String,
+        page: Option<Pagination>,
+        filters: Vec<Filter>,
+        sort_by: Vec<SortBy>,
+    ) -> Result<ResubmittedResults, QueryRunError> {
+        let query_run = self
+            .get_query_run(query_run_id)
+            .await
+            .map_err(QueryRunError::RpcError)?;
+
+        if query_run.archived_at.is_none() {
+            match self
+                .get_query_results(
+                    query_run.id.clone(),
+                    page.clone(),
+                    filters.clone(),
+                    sort_by.clone(),
+                )
+                .await
+            {
+                Ok(results) => {
+                    return Ok(ResubmittedResults {
+                        results,
+                        resubmitted: false,
+                    })
+                }
+                Err(err) if !is_expired(&err) => return Err(QueryRunError::RpcError(err)),
+                Err(_) => {}
+            }
+        }
+
+        let sql_statement = self
+            .get_sql_statement(query_run.sql_statement_id)
+            .await
+            .map_err(QueryRunError::RpcError)?;
+        let query_run = self
+            .run(Query {
+                sql: sql_statement.sql,
+                cache_policy: CachePolicy::BypassCache,
+                tags: query_run.tags,
+                statement_timeout: Some(query_run.statement_timeout_seconds)
+                    .filter(|seconds| *seconds > 0)
+                    .map(Duration::from_secs),
+                ..Default::default()
+            })
             .await?;
 
-        let query_run = res.redirected_to_query_run.unwrap_or(res.query_run);
-
-        self.0
-            .get_query_run_results(GetQueryRunResultsParams {
-                query_run_id: query_run.id,
-                format: QueryFormat::Csv,
-                sort_by,
-                filters,
-                page: Some(page.unwrap_or(Pagination {
-                    number: 1,
-                    size: 100000,
-                })),
+        Ok(ResubmittedResults {
+            results: self
+                .get_query_results(query_run.id, page, filters, sort_by)
+                .await
+                .map_err(QueryRunError::RpcError)?,
+            resubmitted: true,
+        })
+    }
+
+    /// Like `get_query_results`, but rows are kept as raw JSON and parsed on access
+    pub async fn get_query_results_lazy(
+        &self,
+        query_run_id: String,
+        page: Option<Pagination>,
+        filters: Vec<Filter>,
+        sort_by: Vec<SortBy>,
+    ) -> Result<LazyResultSet, ApiError> {
+        let query_run = self.get_query_run(query_run_id).await?;
+        let params = GetQueryRunResultsParams {
+            query_run_id: query_run.id,
+            format: self.result_format,
+            sort_by,
+            filters,
+            page: Some(page.unwrap_or(Pagination {
+                number: 1,
+                size: 100000,
+            })),
+        };
+
+        Ok(retry_not_ready(|| {
+            self.retry_policy
+                .retry(|| self.throttled(self.client.get_query_run_results_raw(params.clone())))
+        })
+        .await?
+        .into())
+    }
+
+    /// Fetches every page of results, keeping them in memory unless the run's
+    /// estimated size exceeds the client's memory budget, in which case rows are
+    /// spilled to a temporary file
+    pub async fn fetch_results(&self, query_run_id: String) -> Result<FetchedResults, FetchError> {
+        let query_run = self
+            .get_query_run(query_run_id)
+            .await
+            .map_err(FetchError::RpcError)?;
+
+        let estimated_size = query_run
+            .total_size
+            .as_deref()
+            .and_then(|size| size.parse::<u64>().ok())
+            .or(query_run
+                .row_count
+                .map(|rows| rows as u64 * ESTIMATED_ROW_SIZE));
+
+        let spill = matches!(
+            (self.memory_budget, estimated_size),
+            (Some(budget), Some(size)) if size > budget
+        );
+
+        if !spill {
+            return self
+                .collect_results(&query_run.id, None)
+                .await
+                .map(FetchedResults::InMemory)
+                .map_err(FetchError::RpcError);
+        }
+
+        let mut writer = SpillWriter::create(&query_run.id).map_err(FetchError::IoError)?;
+        let mut pages = pin!(self.result_pages(query_run.id, PAGE_SIZE));
+
+        while let Some(page) = pages.try_next().await.map_err(FetchError::RpcError)? {
+            writer.append(page).map_err(FetchError::IoError)?;
+        }
+
+        writer
+            .finish()
+            .map(FetchedResults::Spilled)
+            .map_err(FetchError::IoError)
+    }
+
+    /// Rows of a run, fetching each page only once the previous one has been
+    /// consumed
+    pub fn stream_results(
+        &self,
+        query_run_id: String,
+    ) -> impl Stream<Item = Result<Row, ApiError>> + '_ {
+        self.result_pages(query_run_id, PAGE_SIZE)
+            .map_ok(|page| stream::iter(ResultSet::from(page).rows.into_iter().map(Ok)))
+            .try_flatten()
+    }
+
+    /// Pages of results of `page_size` rows, each fetched once the previous one has
+    /// been consumed
+    pub fn result_pages(
+        &self,
+        query_run_id: String,
+        page_size: usize,
+    ) -> impl Stream<Item = Result<GetQueryRunResultsResult, ApiError>> + '_ {
+        self.capped_pages(query_run_id, page_size, self.max_rows)
+    }
+
+    /// Stops after the page that reaches `max_rows`, truncating it
+    fn capped_pages(
+        &self,
+        query_run_id: String,
+        page_size: usize,
+        max_rows: Option<usize>,
+    ) -> impl Stream<Item = Result<GetQueryRunResultsResult, ApiError>> + '_ {
+        stream::try_unfold((Some(PAGE_NUMBER), 0), move |(page_number, rows_read)| {
+            let query_run_id = query_run_id.clone();
+
+            async move {
+                let Some(page_number) = page_number else {
+                    return Ok(None);
+                };
+
+                let mut page = self
+                    .get_results_page(&query_run_id, page_number, page_size)
+                    .await?;
+                let mut next_page =
+                    (page_number < page.page.total_pages).then_some(page_number + 1);
+
+                if let Some(max_rows) = max_rows {
+                    page.rows.truncate(max_rows - rows_read);
+                    if rows_read + page.rows.len() >= max_rows {
+                        next_page = None;
+                    }
+                }
+                let rows_read = rows_read + page.rows.len();
+
+                Ok::<_, ApiError>(Some((page, (next_page, rows_read))))
+            }
+        })
+    }
+
+    /// Like `result_pages`, but up to `parallelism` pages are fetched concurrently.
+    /// Pages are still yielded in order.
+    pub fn result_pages_parallel(
+        &self,
+        query_run_id: String,
+        page_size: usize,
+        parallelism: usize,
+    ) -> impl Stream<Item = Result<GetQueryRunResultsResult, ApiError>> + '_ {
+        let max_rows = self.max_rows;
+        let first_page = {
+            let query_run_id = query_run_id.clone();
+            async move {
+                self.get_results_page(&query_run_id, PAGE_NUMBER, page_size)
+                    .await
+            }
+        };
+
+        stream::once(first_page)
+            .map_ok(move |first_page| {
+                let query_run_id = query_run_id.clone();
+                let last_page = match max_rows {
+                    Some(max_rows) => first_page
+                        .page
+                        .total_pages
+                        .min(max_rows.div_ceil(page_size.max(1))),
+                    None => first_page.page.total_pages,
+                };
+                let rest = (PAGE_NUMBER + 1..=last_page).map(move |page_number| {
+                    let query_run_id = query_run_id.clone();
+                    async move {
+                        self.get_results_page(&query_run_id, page_number, page_size)
+                            .await
+                    }
+                });
+
+                stream::once(future::ready(Ok(first_page)))
+                    .chain(stream::iter(rest).buffered(parallelism.max(1)))
+            })
+            .try_flatten()
+            .scan(0, move |rows_read, page| {
+                let page = page.map(|mut page| {
+                    if let Some(max_rows) = max_rows {
+                        page.rows.truncate(max_rows.saturating_sub(*rows_read));
+                    }
+                    *rows_read += page.rows.len();
+                    page
+                });
+                future::ready(Some(page))
+            })
+    }
+
+    /// Like `get_all_query_results`, fetching up to `parallelism` pages concurrently
+    pub async fn get_all_query_results_parallel(
+        &self,
+        query_run_id: String,
+        parallelism: usize,
+    ) -> Result<ResultSet, ApiError> {
+        let query_run = self.get_query_run(query_run_id).await?;
+
+        self.result_pages_parallel(query_run.id, PAGE_SIZE, parallelism)
+            .try_fold(ResultSet::default(), |mut result_set, page| {
+                let page = ResultSet::from(page);
+                result_set.column_names = page.column_names;
+                result_set.column_types = page.column_types;
+                result_set.rows.extend(page.rows);
+                future::ready(Ok(result_set))
+            })
+            .await
+    }
+
+    /// Returns `cached` untouched if its run hasn't been updated since, otherwise
+    /// fetches the results again
+    pub async fn refetch_results(
+        &self,
+        cached: CachedResults,
+    ) -> Result<RefetchedResults, ApiError> {
+        let query_run = self.get_query_run(cached.query_run.id.clone()).await?;
+
+        if query_run.updated_at == cached.query_run.updated_at
+            && query_run.state == cached.query_run.state
+        {
+            return Ok(RefetchedResults {
+                results: cached,
+                not_modified: true,
+            });
+        }
+
+        let result_set = self.collect_results(&query_run.id, None).await?;
+
+        Ok(RefetchedResults {
+            results: CachedResults {
+                query_run,
+                result_set,
+            },
+            not_modified: false,
+        })
+    }
+
+    /// Fetches the next page described by `state` and advances it. Returns `None`
+    /// once every page has been fetched.
+    pub async fn next_results_page(
+        &self,
+        state: &mut PaginationState,
+    ) -> Result<Option<GetQueryRunResultsResult>, ApiError> {
+        if state.is_done() {
+            return Ok(None);
+        }
+
+        let params = GetQueryRunResultsParams {
+            query_run_id: state.query_run_id.clone(),
+            format: self.result_format,
+            sort_by: state.sort_by.clone(),
+            filters: state.filters.clone(),
+            page: Some(Pagination {
+                number: state.next_page,
+                size: state.page_size,
+            }),
+        };
+        let page = retry_not_ready(|| {
+            self.retry_policy
+                .retry(|| self.throttled(self.client.get_query_run_results(params.clone())))
+        })
+        .await?;
+
+        state.total_pages = Some(page.page.total_pages);
+        state.next_page += 1;
+
+        Ok(Some(page))
+    }
+
+    /// Fetches every page of results, running `validators` on each row as it arrives
+    pub async fn get_validated_results(
+        &self,
+        query_run_id: String,
+        validators: &Validators,
+    ) -> Result<(ResultSet, ValidationReport), ApiError> {
+        let query_run = self.get_query_run(query_run_id).await?;
+        let mut result_set = ResultSet::default();
+        let mut report = ValidationReport::default();
+        let mut pages = pin!(self.result_pages(query_run.id, PAGE_SIZE));
+
+        while let Some(page) = pages.try_next().await? {
+            let page = ResultSet::from(page);
+            for row in page.rows {
+                validators.validate_row(result_set.rows.len(), &row, &mut report);
+                result_set.rows.push(row);
+            }
+            result_set.column_names = page.column_names;
+            result_set.column_types = page.column_types;
+        }
+
+        Ok((result_set, report))
+    }
+
+    async fn get_results_page(
+        &self,
+        query_run_id: &str,
+        page_number: usize,
+        page_size: usize,
+    ) -> Result<GetQueryRunResultsResult, ApiError> {
+        let params = GetQueryRunResultsParams {
+            query_run_id: query_run_id.to_string(),
+            format: self.result_format,
+            sort_by: Vec::new(),
+            filters: Vec::new(),
+            page: Some(Pagination {
+                number: page_number,
+                size: page_size,
+            }),
+        };
+
+        retry_not_ready(|| {
+            self.retry_policy
+                .retry(|| self.throttled(self.client.get_query_run_results(params.clone())))
+        })
+        .await
+    }
+
+    /// `ResultSet::fingerprint` of a run's results, or `fingerprint_unordered`,
+    /// computed a page at a time without keeping the rows, to check a re-run or
+    /// cached result against a recorded fingerprint. Covers every row, following
+    /// the run's redirect and ignoring the client's `max_rows`.
+    pub async fn result_fingerprint(
+        &self,
+        query_run_id: String,
+        ordered: bool,
+    ) -> Result<u64, ApiError> {
+        let query_run = self.get_query_run(query_run_id).await?;
+        let mut pages = pin!(self.capped_pages(query_run.id, PAGE_SIZE, None));
+        let mut fingerprinter = None;
+
+        while let Some(page) = pages.next().await {
+            let page = ResultSet::from(page?);
+            fingerprinter
+                .get_or_insert_with(|| Fingerprinter::new(&page.column_names, ordered))
+                .update(&page.rows);
+        }
+
+        Ok(fingerprinter
+            .unwrap_or_else(|| Fingerprinter::new(&[], ordered))
+            .finish())
+    }
+
+    pub(crate) async fn collect_results(
+        &self,
+        query_run_id: &str,
+        max_rows: Option<usize>,
+    ) -> Result<ResultSet, ApiError> {
+        let max_rows = match (max_rows, self.max_rows) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        self.collect_pages(query_run_id.to_string(), max_rows).await
+    }
+
+    /// Every row of a run's results, following its redirect and ignoring the
+    /// client's `max_rows`, for comparisons that must cover the whole result
+    pub(crate) async fn collect_all_results(
+        &self,
+        query_run_id: String,
+    ) -> Result<ResultSet, ApiError> {
+        let query_run = self.get_query_run(query_run_id).await?;
+        self.collect_pages(query_run.id, None).await
+    }
+
+    async fn collect_pages(
+        &self,
+        query_run_id: String,
+        max_rows: Option<usize>,
+    ) -> Result<ResultSet, ApiError> {
+        self.capped_pages(query_run_id, PAGE_SIZE, max_rows)
+            .try_fold(ResultSet::default(), |mut result_set, page| {
+                let page = ResultSet::from(page);
+                result_set.column_names = page.column_names;
+                result_set.column_types = page.column_types;
+                result_set.rows.extend(page.rows);
+                future::ready(Ok(result_set))
             })
             .await
     }
 }
+
+/// Whether the API rejected a results request because the run hasn't finished
+/// writing its results yet
+pub fn is_not_ready(err: &ClientError) -> bool {
+    let ClientError::Call(err) = err else {
+        return false;
+    };
+    let message = err.message().to_lowercase();
+    ["not ready", "not finished", "notfinished", "still running"]
+        .iter()
+        .any(|pattern| message.contains(pattern))
+}
+
+/// Whether the API rejected a results request because the run's results were
+/// archived or have expired
+pub fn is_expired(err: &ApiError) -> bool {
+    let (ApiError::NotFound { message }
+    | ApiError::UserError { message, .. }
+    | ApiError::ServerError { message, .. }) = err
+    else {
+        return false;
+    };
+    let message = message.to_lowercase();
+    ["archived", "expired"]
+        .iter()
+        .any(|pattern| message.contains(pattern))
+}
+
+/// Retries `request` with exponential backoff, up to `NOT_READY_RETRIES` times,
+/// while it fails because the results aren't ready yet
+async fn retry_not_ready<T, F, Fut>(mut request: F) -> Result<T, ApiError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, ClientError>>,
+{
+    let mut retry_interval = RETRY_INTERVAL;
+
+    for _ in 0..NOT_READY_RETRIES {
+        match request().await {
+            Err(err) if is_not_ready(&err) => {
+                tokio::time::sleep(retry_interval).await;
+                retry_interval *= 2;
+            }
+            res => return res.map_err(Into::into),
+        }
+    }
+
+    request().await.map_err(Into::into)
+}
@@ -0,0 +1,149 @@
+use crate::flipside::Flipside;
+use crate::registry::{ParamType, QueryRegistry};
+use crate::result_set::ResultSet;
+use async_graphql::dynamic::{
+    Field, FieldFuture, FieldValue, InputValue, Object, Scalar, Schema, SchemaError, TypeRef,
+};
+use async_graphql::{Error, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+pub const QUERY_TYPE: &str = "Query";
+pub const RESULT_TYPE: &str = "QueryResult";
+pub const JSON_SCALAR: &str = "JSON";
+
+/// Builds a GraphQL schema with one field per registered query. Each field takes
+/// the query's declared parameters as arguments, optional when the manifest has a
+/// default, and resolves to the query's results:
+///
+/// ```graphql
+/// type QueryResult {
+///   columnNames: [String!]!
+///   columnTypes: [String!]!
+///   rows: [JSON!]!
+/// }
+/// ```
+pub fn registry_schema(
+    flipside: Flipside,
+    registry: Arc<QueryRegistry>,
+) -> Result<Schema, SchemaError> {
+    let mut query = Object::new(QUERY_TYPE);
+    let mut names: Vec<_> = registry.names().collect();
+    names.sort_unstable();
+
+    for name in names {
+        let definition = registry.get(name).unwrap();
+        let mut field = Field::new(name, TypeRef::named_nn(RESULT_TYPE), {
+            let flipside = flipside.clone();
+            let registry = registry.clone();
+            let name = name.to_string();
+
+            move |ctx| {
+                let flipside = flipside.clone();
+                let registry = registry.clone();
+                let name = name.clone();
+
+                FieldFuture::new(async move {
+                    let params = ctx
+                        .args
+                        .iter()
+                        .map(|(param, value)| {
+                            Ok((param.to_string(), value.as_value().clone().into_json()?))
+                        })
+                        .collect::<Result<HashMap<_, _>, Error>>()?;
+
+                    let result_set = registry
+                        .run_named(&flipside, &name, &params)
+                        .await
+                        .map_err(|err| Error::new(err.to_string()))?;
+
+                    Ok(Some(FieldValue::owned_any(result_set)))
+                })
+            }
+        });
+
+        let mut params: Vec<_> = definition.param_types.iter().collect();
+        params.sort_unstable_by_key(|(param, _)| *param);
+        for (param, param_type) in params {
+            let type_name = match param_type {
+                ParamType::String | ParamType::Date => TypeRef::STRING,
+                ParamType::Number => TypeRef::FLOAT,
+                ParamType::Integer => TypeRef::INT,
+                ParamType::Boolean => TypeRef::BOOLEAN,
+            };
+            let type_ref = if definition.params.contains_key(param) {
+                TypeRef::named(type_name)
+            } else {
+                TypeRef::named_nn(type_name)
+            };
+            field = field.argument(InputValue::new(param, type_ref));
+        }
+
+        query = query.field(field);
+    }
+
+    Schema::build(QUERY_TYPE, None, None)
+        .register(query)
+        .register(result_type())
+        .register(Scalar::new(JSON_SCALAR))
+        .finish()
+}
+
+fn result_type() -> Object {
+    Object::new(RESULT_TYPE)
+        .field(Field::new(
+            "columnNames",
+            TypeRef::named_nn_list_nn(TypeRef::STRING),
+            |ctx| {
+                FieldFuture::new(async move {
+                    let result_set = ctx.parent_value.try_downcast_ref::<ResultSet>()?;
+                    Ok(Some(FieldValue::list(
+                        result_set
+                            .column_names
+                            .iter()
+                            .map(|name| FieldValue::value(name.as_str())),
+                    )))
+                })
+            },
+        ))
+        .field(Field::new(
+            "columnTypes",
+            TypeRef::named_nn_list_nn(TypeRef::STRING),
+            |ctx| {
+                FieldFuture::new(async move {
+                    let result_set = ctx.parent_value.try_downcast_ref::<ResultSet>()?;
+                    Ok(Some(FieldValue::list(
+                        result_set
+                            .column_types
+                            .iter()
+                            .map(|column_type| {
+                                Ok(FieldValue::value(Value::from_json(serde_json::to_value(
+                                    column_type,
+                                )?)?))
+                            })
+                            .collect::<Result<Vec<_>, Error>>()?,
+                    )))
+                })
+            },
+        ))
+        .field(Field::new(
+            "rows",
+            TypeRef::named_nn_list_nn(JSON_SCALAR),
+            |ctx| {
+                FieldFuture::new(async move {
+                    let result_set = ctx.parent_value.try_downcast_ref::<ResultSet>()?;
+                    Ok(Some(FieldValue::list(
+                        result_set
+                            .rows
+                            .iter()
+                            .map(|row| {
+                                Ok(FieldValue::value(Value::from_json(
+                                    serde_json::Value::Array(row.clone()),
+                                )?))
+                            })
+                            .collect::<Result<Vec<_>, Error>>()?,
+                    )))
+                })
+            },
+        ))
+}
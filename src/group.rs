@@ -0,0 +1,96 @@
+use crate::flipside::{CancellationToken, Flipside, Query, QueryRunError};
+use crate::rpc::QueryRun;
+use std::panic;
+use tokio::task::JoinSet;
+
+/// Queries run as tasks of a group that can be awaited together and cancelled
+/// together. Cancelling the group, or dropping it, also cancels the runs that are
+/// still going server-side.
+pub struct QueryGroup {
+    flipside: Flipside,
+    tasks: JoinSet<(usize, Result<QueryRun, QueryRunError>)>,
+    token: CancellationToken,
+    spawned: usize,
+}
+
+impl QueryGroup {
+    pub fn new(flipside: Flipside) -> Self {
+        Self {
+            flipside,
+            tasks: JoinSet::new(),
+            token: CancellationToken::new(),
+            spawned: 0,
+        }
+    }
+
+    /// Starts running `query`, returning its index in the group
+    pub fn spawn(&mut self, query: Query) -> usize {
+        let index = self.spawned;
+        self.spawned += 1;
+
+        let flipside = self.flipside.clone();
+        let token = self.token.clone();
+        self.tasks.spawn(async move {
+            let res = flipside.run_with_cancellation(query, &token, true).await;
+            (index, res)
+        });
+        index
+    }
+
+    /// Number of queries that haven't been returned by `next_completed` yet
+    pub fn len(&self) -> usize {
+        self.tasks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+
+    /// The next query to finish with its index, `None` once all have been returned.
+    /// Queries whose task was cancelled, e.g. by the runtime shutting down, are
+    /// skipped.
+    pub async fn next_completed(&mut self) -> Option<(usize, Result<QueryRun, QueryRunError>)> {
+        loop {
+            match self.tasks.join_next().await? {
+                Ok(completed) => return Some(completed),
+                Err(err) if err.is_panic() => panic::resume_unwind(err.into_panic()),
+                Err(_) => {}
+            }
+        }
+    }
+
+    /// Waits for every remaining query, returning their outcomes in spawn order,
+    /// without those `next_completed` skips
+    pub async fn join_all(mut self) -> Vec<Result<QueryRun, QueryRunError>> {
+        let mut completed = Vec::with_capacity(self.tasks.len());
+        while let Some(next) = self.next_completed().await {
+            completed.push(next);
+        }
+
+        completed.sort_by_key(|(index, _)| *index);
+        completed.into_iter().map(|(_, res)| res).collect()
+    }
+
+    /// Stops every query of the group, cancelling their runs server-side. They
+    /// complete with `QueryRunError::Aborted`.
+    pub fn cancel(&self) {
+        self.token.cancel();
+    }
+
+    /// Token cancelling the group, e.g. to tie it to a parent task's shutdown
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.token.clone()
+    }
+}
+
+impl Drop for QueryGroup {
+    fn drop(&mut self) {
+        if self.tasks.is_empty() {
+            return;
+        }
+
+        // Let the tasks cancel their runs instead of aborting them mid-request
+        self.token.cancel();
+        self.tasks.detach_all();
+    }
+}
@@ -0,0 +1,295 @@
+use crate::error::ApiError;
+use crate::flipside::{ExecutionError, Flipside, QueryRunError};
+use crate::poll::PollStrategy;
+use crate::rpc::{QueryRun, QueryState};
+use futures::future::{self, Either};
+use std::collections::HashMap;
+use std::pin::pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{watch, OwnedSemaphorePermit};
+use tokio_util::sync::CancellationToken;
+
+/// Average client-observed runtimes of successful runs, keyed by SQL statement
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RuntimeHistory(Arc<Mutex<HashMap<String, (Duration, u32)>>>);
+
+impl RuntimeHistory {
+    pub(crate) fn record(&self, sql_statement_id: &str, runtime: Duration) {
+        let mut history = self.0.lock().unwrap();
+        let (total, count) = history
+            .entry(sql_statement_id.to_string())
+            .or_insert((Duration::ZERO, 0));
+        *total += runtime;
+        *count += 1;
+    }
+
+    pub(crate) fn average(&self, sql_statement_id: &str) -> Option<Duration> {
+        self.0
+            .lock()
+            .unwrap()
+            .get(sql_statement_id)
+            .map(|(total, count)| *total / *count)
+    }
+}
+
+/// A submitted query run that can be polled or awaited. With `cancel_on_drop`,
+/// dropping the handle before the run finishes cancels it server-side.
+pub struct QueryRunHandle {
+    pub(crate) flipside: Flipside,
+    pub(crate) query_run_id: String,
+    pub(crate) query_run: QueryRun,
+    pub(crate) submitted_at: Instant,
+    pub(crate) poll_strategy: Arc<dyn PollStrategy>,
+    pub(crate) timeout: Duration,
+    pub(crate) cancel_on_timeout: bool,
+    pub(crate) cancel_on_drop: bool,
+    pub(crate) on_state_change: Option<StateCallback>,
+    pub(crate) state_tx: Option<watch::Sender<QueryState>>,
+    /// Slot of the client's concurrency limit, released once the run finishes
+    pub(crate) run_slot: Option<OwnedSemaphorePermit>,
+    /// Updates from the client's shared poller, if it has one
+    pub(crate) shared_updates: Option<watch::Receiver<Option<QueryRun>>>,
+}
+
+pub(crate) type StateCallback = Box<dyn FnMut(&QueryRun, Duration) + Send>;
+
+impl QueryRunHandle {
+    /// Cancels the run if the handle is dropped before it finishes, e.g. because
+    /// the task awaiting it was aborted. Requires a Tokio runtime at drop time.
+    pub fn cancel_on_drop(mut self) -> Self {
+        self.cancel_on_drop = true;
+        self
+    }
+
+    /// Calls `f` with the run and the time since submission whenever a poll
+    /// observes a new state
+    pub fn on_state_change(mut self, f: impl FnMut(&QueryRun, Duration) + Send + 'static) -> Self {
+        self.on_state_change = Some(Box::new(f));
+        self
+    }
+
+    /// Receiver updated whenever a poll observes a new state
+    pub fn watch_state(&mut self) -> watch::Receiver<QueryState> {
+        self.state_tx
+            .get_or_insert_with(|| watch::channel(self.query_run.state.clone()).0)
+            .subscribe()
+    }
+
+    pub fn id(&self) -> &str {
+        &self.query_run_id
+    }
+
+    /// The run as of the last poll
+    pub fn query_run(&self) -> &QueryRun {
+        &self.query_run
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.submitted_at.elapsed()
+    }
+
+    /// Time left based on previous runs of the same SQL statement through this
+    /// client, `None` if it has never completed before
+    pub fn estimated_remaining(&self) -> Option<Duration> {
+        self.flipside
+            .runtimes
+            .average(&self.query_run.sql_statement_id)
+            .map(|average| average.saturating_sub(self.elapsed()))
+    }
+
+    /// Refreshes the run's state
+    pub async fn poll(&mut self) -> Result<&QueryRun, ApiError> {
+        let query_run = self
+            .flipside
+            .get_query_run(self.query_run_id.clone())
+            .await?;
+        self.update(query_run);
+        Ok(&self.query_run)
+    }
+
+    /// Records a freshly fetched state of the run, notifying observers
+    pub(crate) fn update(&mut self, query_run: QueryRun) {
+        let previous_state = std::mem::replace(&mut self.query_run, query_run).state;
+
+        if self.query_run.state != previous_state {
+            let elapsed = self.elapsed();
+            if let Some(on_state_change) = &mut self.on_state_change {
+                on_state_change(&self.query_run, elapsed);
+            }
+            if let Some(state_tx) = &self.state_tx {
+                state_tx.send_replace(self.query_run.state.clone());
+            }
+        }
+
+        if self.query_run.state.is_terminal() {
+            self.run_slot = None;
+        }
+        if self.query_run.state == QueryState::QueryStateSuccess {
+            self.flipside
+                .runtimes
+                .record(&self.query_run.sql_statement_id, self.elapsed());
+        }
+    }
+
+    /// Polls until the run succeeds, fails or times out
+    pub async fn wait(mut self) -> Result<QueryRun, QueryRunError> {
+        self.wait_until(QueryState::QueryStateSuccess).await?;
+        Ok(self.query_run.clone())
+    }
+
+    /// Like `wait`, but stops waiting once `token` is cancelled, cancelling the run
+    /// server-side too if `cancel_remote` is set
+    pub async fn wait_with_cancellation(
+        mut self,
+        token: &CancellationToken,
+        cancel_remote: bool,
+    ) -> Result<QueryRun, QueryRunError> {
+        let aborted = {
+            let wait = pin!(self.wait_until(QueryState::QueryStateSuccess));
+            match future::select(wait, pin!(token.cancelled())).await {
+                Either::Left((res, _)) => res.map(|_| false)?,
+                Either::Right(_) => true,
+            }
+        };
+
+        if !aborted {
+            return Ok(self.query_run.clone());
+        }
+
+        if cancel_remote {
+            if let Ok(query_run) = self
+                .flipside
+                .cancel_query_run(self.query_run_id.clone())
+                .await
+            {
+                self.query_run = query_run;
+            }
+        }
+
+        Err(QueryRunError::Aborted {
+            query_run: Box::new(self.query_run.clone()),
+        })
+    }
+
+    /// Polls until the run reaches `state` or a later one, e.g. to react as soon as
+    /// results start streaming. Fails if the run fails, is cancelled or times out
+    /// first.
+    pub async fn wait_until(&mut self, state: QueryState) -> Result<&QueryRun, QueryRunError> {
+        let mut attempt = 0;
+        let start = Instant::now();
+        if let Some(shared_poller) = &self.flipside.shared_poller {
+            self.shared_updates = Some(shared_poller.subscribe(&self.flipside, &self.query_run));
+        }
+
+        loop {
+            let query_run = self.refresh().await.map_err(QueryRunError::RpcError)?;
+
+            if reached(&query_run.state, &state) {
+                break;
+            }
+
+            match query_run.state {
+                QueryState::QueryStateFailed => {
+                    return Err(QueryRunError::ExecutionError(ExecutionError::from(
+                        query_run,
+                    )));
+                }
+                QueryState::QueryStateCancelled => {
+                    return Err(QueryRunError::Cancelled(Box::new(query_run.clone())));
+                }
+
+                _ => {}
+            };
+
+            let elapsed = start.elapsed();
+            if elapsed >= self.timeout {
+                if self.cancel_on_timeout {
+                    if let Ok(query_run) = self
+                        .flipside
+                        .cancel_query_run(self.query_run_id.clone())
+                        .await
+                    {
+                        self.query_run = query_run;
+                    }
+                }
+                return Err(QueryRunError::Timeout {
+                    query_run: Box::new(self.query_run.clone()),
+                    elapsed,
+                });
+            }
+
+            self.pause(attempt, self.timeout - elapsed).await;
+            attempt = attempt.saturating_add(1);
+        }
+
+        Ok(&self.query_run)
+    }
+
+    /// Takes the shared poller's latest state of the run, or polls it if there's
+    /// no shared poller or it failed to fetch the run
+    async fn refresh(&mut self) -> Result<&QueryRun, ApiError> {
+        let shared = self
+            .shared_updates
+            .as_mut()
+            .and_then(|updates| updates.borrow_and_update().clone());
+
+        match shared {
+            Some(query_run) => {
+                self.update(query_run);
+                Ok(&self.query_run)
+            }
+            None => {
+                self.shared_updates = None;
+                self.poll().await
+            }
+        }
+    }
+
+    /// Waits for the shared poller's next update, or the poll strategy's interval,
+    /// but no longer than `remaining`
+    async fn pause(&mut self, attempt: u32, remaining: Duration) {
+        match &mut self.shared_updates {
+            Some(updates) => {
+                if let Ok(Err(_)) = tokio::time::timeout(remaining, updates.changed()).await {
+                    self.shared_updates = None;
+                }
+            }
+            None => tokio::time::sleep(self.poll_strategy.interval(attempt).min(remaining)).await,
+        }
+    }
+}
+
+/// Whether a run in `current` has gone through `target`
+fn reached(current: &QueryState, target: &QueryState) -> bool {
+    fn progress(state: &QueryState) -> Option<u8> {
+        match state {
+            QueryState::QueryStateReady => Some(0),
+            QueryState::QueryStateRunning => Some(1),
+            QueryState::QueryStateStreamingResults => Some(2),
+            QueryState::QueryStateSuccess => Some(3),
+            QueryState::QueryStateFailed | QueryState::QueryStateCancelled => None,
+        }
+    }
+
+    match (progress(current), progress(target)) {
+        (Some(current), Some(target)) => current >= target,
+        _ => current == target,
+    }
+}
+
+impl Drop for QueryRunHandle {
+    fn drop(&mut self) {
+        if !self.cancel_on_drop || self.query_run.state.is_terminal() {
+            return;
+        }
+
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            let flipside = self.flipside.clone();
+            let query_run_id = self.query_run_id.clone();
+            runtime.spawn(async move {
+                let _ = flipside.cancel_query_run(query_run_id).await;
+            });
+        }
+    }
+}
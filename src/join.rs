@@ -0,0 +1,197 @@
+use crate::result_set::{ResultSet, Row};
+use serde_json::Value;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinKind {
+    Inner,
+    Left,
+    Right,
+    Full,
+}
+
+pub(crate) fn join(
+    left: &ResultSet,
+    right: &ResultSet,
+    on: &[&str],
+    kind: JoinKind,
+) -> Option<ResultSet> {
+    let left_keys = on
+        .iter()
+        .map(|column| left.column_index(column))
+        .collect::<Option<Vec<_>>>()?;
+    let right_keys = on
+        .iter()
+        .map(|column| right.column_index(column))
+        .collect::<Option<Vec<_>>>()?;
+    let right_values = (0..right.column_names.len())
+        .filter(|i| !right_keys.contains(i))
+        .collect::<Vec<_>>();
+
+    let mut column_names = left.column_names.clone();
+    let mut column_types = left.column_types.clone();
+    for &i in &right_values {
+        let name = &right.column_names[i];
+        column_names.push(if left.column_index(name).is_some() {
+            format!("{}_right", name)
+        } else {
+            name.clone()
+        });
+        if let Some(column_type) = right.column_types.get(i) {
+            column_types.push(column_type.clone());
+        }
+    }
+
+    // Like in SQL, null keys match nothing, not even other nulls
+    let key = |row: &Row, keys: &[usize]| {
+        keys.iter()
+            .map(|&i| {
+                row.get(i)
+                    .filter(|value| !value.is_null())
+                    .map(Value::to_string)
+            })
+            .collect::<Option<Vec<_>>>()
+    };
+
+    let mut index: HashMap<Vec<String>, Vec<usize>> = HashMap::new();
+    for (i, row) in right.rows.iter().enumerate() {
+        if let Some(key) = key(row, &right_keys) {
+            index.entry(key).or_default().push(i);
+        }
+    }
+
+    let left_width = left.column_names.len();
+    let mut matched = vec![false; right.rows.len()];
+    let mut rows = Vec::new();
+
+    for row in &left.rows {
+        let matches = key(row, &left_keys).and_then(|key| index.get(&key));
+
+        match matches {
+            Some(matches) => {
+                for &i in matches {
+                    matched[i] = true;
+                    let mut joined = row.clone();
+                    joined.resize(left_width, Value::Null);
+                    joined.extend(
+                        right_values
+                            .iter()
+                            .map(|&j| right.rows[i].get(j).cloned().unwrap_or(Value::Null)),
+                    );
+                    rows.push(joined);
+                }
+            }
+            None if matches!(kind, JoinKind::Left | JoinKind::Full) => {
+                let mut joined = row.clone();
+                joined.resize(left_width + right_values.len(), Value::Null);
+                rows.push(joined);
+            }
+            None => {}
+        }
+    }
+
+    if matches!(kind, JoinKind::Right | JoinKind::Full) {
+        for (i, row) in right.rows.iter().enumerate().filter(|(i, _)| !matched[*i]) {
+            let mut joined = vec![Value::Null; left_width];
+            for (&left_key, &right_key) in left_keys.iter().zip(&right_keys) {
+                joined[left_key] = row.get(right_key).cloned().unwrap_or(Value::Null);
+            }
+            joined.extend(
+                right_values
+                    .iter()
+                    .map(|&j| right.rows[i].get(j).cloned().unwrap_or(Value::Null)),
+            );
+            rows.push(joined);
+        }
+    }
+
+    Some(ResultSet {
+        column_names,
+        column_types,
+        rows,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc::ColumnType;
+    use serde_json::json;
+
+    fn result_set(columns: [&str; 2], rows: Vec<[Value; 2]>) -> ResultSet {
+        ResultSet {
+            column_names: columns.map(String::from).to_vec(),
+            column_types: vec![ColumnType::Unknown; 2],
+            rows: rows.into_iter().map(Vec::from).collect(),
+        }
+    }
+
+    fn tokens() -> ResultSet {
+        result_set(
+            ["token", "price"],
+            vec![
+                [json!("eth"), json!(3000)],
+                [json!("btc"), json!(60000)],
+                [Value::Null, json!(0)],
+            ],
+        )
+    }
+
+    fn transfers() -> ResultSet {
+        result_set(
+            ["token", "amount"],
+            vec![
+                [json!("eth"), json!(1)],
+                [json!("eth"), json!(2)],
+                [json!("sol"), json!(3)],
+                [Value::Null, json!(4)],
+            ],
+        )
+    }
+
+    #[test]
+    fn inner_joins_match_every_pair() {
+        let joined = transfers()
+            .join(&tokens(), &["token"], JoinKind::Inner)
+            .unwrap();
+        assert_eq!(joined.column_names, ["token", "amount", "price"]);
+        assert_eq!(
+            joined.rows,
+            [
+                vec![json!("eth"), json!(1), json!(3000)],
+                vec![json!("eth"), json!(2), json!(3000)],
+            ]
+        );
+        assert!(transfers()
+            .join(&tokens(), &["missing"], JoinKind::Inner)
+            .is_none());
+    }
+
+    #[test]
+    fn null_keys_match_nothing() {
+        let left = transfers()
+            .join(&tokens(), &["token"], JoinKind::Left)
+            .unwrap();
+        assert_eq!(left.rows.len(), 4);
+        assert_eq!(left.rows[3], [Value::Null, json!(4), Value::Null]);
+
+        let full = transfers()
+            .join(&tokens(), &["token"], JoinKind::Full)
+            .unwrap();
+        assert_eq!(full.rows.len(), 6);
+        assert!(full
+            .rows
+            .contains(&vec![json!("btc"), Value::Null, json!(60000)]));
+        assert!(full
+            .rows
+            .contains(&vec![Value::Null, Value::Null, json!(0)]));
+    }
+
+    #[test]
+    fn keys_of_different_types_do_not_match() {
+        let ids = result_set(["id", "name"], vec![[json!(1), json!("a")]]);
+        let string_ids = result_set(["id", "score"], vec![[json!("1"), json!(10)]]);
+        let joined = ids.join(&string_ids, &["id"], JoinKind::Left).unwrap();
+        assert_eq!(joined.rows, [vec![json!(1), json!("a"), Value::Null]]);
+    }
+}
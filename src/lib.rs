@@ -1,3 +1,59 @@
+pub mod aggregate;
+#[cfg(feature = "arrow")]
+pub mod arrow;
+#[cfg(feature = "avro")]
+pub mod avro;
+pub mod backfill;
+pub mod csv;
+mod dedup;
 pub mod defaults;
+pub mod diff;
+#[cfg(feature = "download")]
+pub mod download;
+pub mod error;
+pub mod expectations;
+pub mod export;
 pub mod flipside;
+#[cfg(feature = "graphql")]
+pub mod graphql;
+pub mod group;
+pub mod handle;
+pub mod join;
+#[cfg(feature = "object_store")]
+pub mod object_store;
+#[cfg(feature = "openrpc")]
+pub mod openrpc;
+pub mod pagination;
+#[cfg(feature = "parquet")]
+pub mod parquet;
+pub mod partition;
+pub mod pivot;
+#[cfg(feature = "polars")]
+pub mod polars;
+pub mod poll;
+mod poller;
+mod rate_limit;
+pub mod registry;
+pub mod result_set;
+pub mod retry;
+pub mod row;
 pub mod rpc;
+pub mod schema;
+pub mod spill;
+pub mod sql;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod stats;
+pub mod stream;
+pub mod tags;
+#[cfg(feature = "templates")]
+pub mod template;
+pub mod validation;
+pub mod watch;
+#[cfg(feature = "axum")]
+pub mod web;
+
+#[doc(hidden)]
+pub use include_dir;
+#[doc(hidden)]
+pub use serde_json;
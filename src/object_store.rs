@@ -0,0 +1,35 @@
+use crate::export::{ExportError, ExportFormat};
+use crate::flipside::Flipside;
+use object_store::buffered::BufWriter;
+use object_store::path::Path;
+use object_store::ObjectStore;
+use std::sync::Arc;
+use tokio::io::AsyncWriteExt;
+
+impl Flipside {
+    /// Writes the results of a run in `format` to `path` in `store`, uploading
+    /// in parts as pages come in. The upload is aborted if the export fails, so
+    /// no partial object is left behind. Returns the number of rows written.
+    pub async fn export_to(
+        &self,
+        query_run_id: String,
+        store: Arc<dyn ObjectStore>,
+        path: Path,
+        format: ExportFormat,
+    ) -> Result<usize, ExportError> {
+        let mut writer = BufWriter::new(store, path);
+
+        let res = match self.export(query_run_id, &mut writer, format).await {
+            Ok(rows) => writer
+                .shutdown()
+                .await
+                .map(|()| rows)
+                .map_err(ExportError::IoError),
+            Err(err) => Err(err),
+        };
+        if res.is_err() {
+            let _ = writer.abort().await;
+        }
+        res
+    }
+}
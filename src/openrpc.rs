@@ -0,0 +1,56 @@
+use crate::rpc::{
+    CancelQueryRunResult, CreateQueryRunParams, CreateQueryRunResult, GetQueryRunResult,
+    GetQueryRunResultsParams, GetQueryRunResultsResult, GetSqlStatementResult, QueryRunIdParams,
+    SqlStatementIdParams,
+};
+use schemars::gen::{SchemaGenerator, SchemaSettings};
+use schemars::JsonSchema;
+use serde_json::{json, Value};
+
+pub const OPENRPC_VERSION: &str = "1.2.6";
+
+/// OpenRPC document describing the methods of the `Rpc` trait, with the model
+/// types under `components.schemas`
+pub fn document() -> Value {
+    let mut gen = SchemaSettings::draft07()
+        .with(|settings| settings.definitions_path = "#/components/schemas/".to_string())
+        .into_generator();
+
+    let methods = vec![
+        method::<GetQueryRunResultsParams, GetQueryRunResultsResult>(
+            &mut gen,
+            "getQueryRunResults",
+        ),
+        method::<CreateQueryRunParams, CreateQueryRunResult>(&mut gen, "createQueryRun"),
+        method::<QueryRunIdParams, GetQueryRunResult>(&mut gen, "getQueryRun"),
+        method::<SqlStatementIdParams, GetSqlStatementResult>(&mut gen, "getSqlStatement"),
+        method::<QueryRunIdParams, CancelQueryRunResult>(&mut gen, "cancelQueryRun"),
+    ];
+
+    json!({
+        "openrpc": OPENRPC_VERSION,
+        "info": {
+            "title": "Flipside API",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "servers": [{ "name": "flipside", "url": crate::defaults::API_BASE_URL }],
+        "methods": methods,
+        "components": { "schemas": gen.take_definitions() },
+    })
+}
+
+fn method<P: JsonSchema, R: JsonSchema>(gen: &mut SchemaGenerator, name: &str) -> Value {
+    json!({
+        "name": name,
+        "paramStructure": "by-position",
+        "params": [{
+            "name": "params",
+            "required": true,
+            "schema": gen.subschema_for::<P>(),
+        }],
+        "result": {
+            "name": "result",
+            "schema": gen.subschema_for::<R>(),
+        },
+    })
+}
@@ -0,0 +1,49 @@
+use crate::defaults::{PAGE_NUMBER, PAGE_SIZE};
+use crate::rpc::{Filter, SortBy};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Progress through the pages of a query run's results, which can be saved to
+/// disk and loaded again to resume an interrupted download
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PaginationState {
+    pub query_run_id: String,
+    pub page_size: usize,
+    /// The next page to fetch, starting at 1
+    pub next_page: usize,
+    /// Known once the first page has been fetched
+    pub total_pages: Option<usize>,
+    pub filters: Vec<Filter>,
+    pub sort_by: Vec<SortBy>,
+}
+
+impl PaginationState {
+    pub fn new(query_run_id: String) -> Self {
+        Self {
+            query_run_id,
+            page_size: PAGE_SIZE,
+            next_page: PAGE_NUMBER,
+            total_pages: None,
+            filters: Vec::new(),
+            sort_by: Vec::new(),
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        matches!(self.total_pages, Some(total_pages) if self.next_page > total_pages)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(serde_json::from_slice(&fs::read(path)?)?)
+    }
+
+    /// Writes the state atomically, so a crash mid-write never leaves a corrupt file
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec(self)?)?;
+        fs::rename(tmp, path)
+    }
+}
@@ -0,0 +1,116 @@
+use crate::arrow::RecordBatchError;
+use crate::defaults::PAGE_SIZE;
+use crate::error::ApiError;
+use crate::flipside::Flipside;
+use arrow::datatypes::Schema;
+use arrow::error::ArrowError;
+use futures::stream::StreamExt;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::{Compression, ZstdLevel};
+use parquet::errors::ParquetError;
+use parquet::file::properties::WriterProperties;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::pin::pin;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy)]
+pub struct ParquetOptions {
+    /// Maximum rows per row group
+    pub row_group_size: usize,
+    pub compression: ParquetCompression,
+}
+
+impl Default for ParquetOptions {
+    fn default() -> Self {
+        Self {
+            row_group_size: PAGE_SIZE,
+            compression: ParquetCompression::Snappy,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParquetCompression {
+    Uncompressed,
+    Snappy,
+    /// Zstandard at the given level, 1 to 22
+    Zstd(i32),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ParquetExportError {
+    #[error(transparent)]
+    RpcError(#[from] ApiError),
+    #[error("failed to create the parquet file")]
+    IoError(#[source] io::Error),
+    #[error(transparent)]
+    ArrowError(#[from] ArrowError),
+    #[error(transparent)]
+    ParquetError(#[from] ParquetError),
+}
+
+impl From<RecordBatchError> for ParquetExportError {
+    fn from(err: RecordBatchError) -> Self {
+        match err {
+            RecordBatchError::RpcError(err) => ParquetExportError::RpcError(err),
+            RecordBatchError::ArrowError(err) => ParquetExportError::ArrowError(err),
+        }
+    }
+}
+
+impl Flipside {
+    /// Writes the results of a run to a Parquet file at `path`, one page at a time
+    /// so they never have to fit in memory. Returns the number of rows written.
+    pub async fn export_parquet(
+        &self,
+        query_run_id: String,
+        path: impl AsRef<Path>,
+        options: ParquetOptions,
+    ) -> Result<usize, ParquetExportError> {
+        let compression = match options.compression {
+            ParquetCompression::Uncompressed => Compression::UNCOMPRESSED,
+            ParquetCompression::Snappy => Compression::SNAPPY,
+            ParquetCompression::Zstd(level) => Compression::ZSTD(ZstdLevel::try_new(level)?),
+        };
+        let properties = WriterProperties::builder()
+            .set_max_row_group_size(options.row_group_size.max(1))
+            .set_compression(compression)
+            .build();
+
+        let mut batches = pin!(self.record_batches(query_run_id));
+        let mut writer = None;
+        let mut rows = 0;
+
+        while let Some(batch) = batches.next().await {
+            let batch = batch?;
+            let writer = match &mut writer {
+                Some(writer) => writer,
+                None => writer.insert(new_writer(
+                    path.as_ref(),
+                    batch.schema(),
+                    properties.clone(),
+                )?),
+            };
+            rows += batch.num_rows();
+            writer.write(&batch)?;
+        }
+
+        let writer = match writer {
+            Some(writer) => writer,
+            None => new_writer(path.as_ref(), Arc::new(Schema::empty()), properties)?,
+        };
+        writer.close()?;
+        Ok(rows)
+    }
+}
+
+fn new_writer(
+    path: &Path,
+    schema: Arc<Schema>,
+    properties: WriterProperties,
+) -> Result<ArrowWriter<File>, ParquetExportError> {
+    let file = File::create(path).map_err(ParquetExportError::IoError)?;
+    Ok(ArrowWriter::try_new(file, schema, Some(properties))?)
+}
@@ -0,0 +1,250 @@
+use crate::defaults::PAGE_SIZE;
+use crate::error::ApiError;
+use crate::flipside::{Flipside, Query, QueryRunError};
+use crate::result_set::{ResultSet, Row};
+use crate::retry::RunRetryPolicy;
+use crate::rpc::QueryRun;
+use futures::stream::{self, Stream, StreamExt, TryStreamExt};
+use std::ops::Range;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Splits a query over a time column into windows run as separate queries, to stay
+/// under single-run timeouts and result size limits. Results are merged in window
+/// order.
+#[derive(Debug, Clone)]
+pub struct TimePartitioner {
+    /// Query to partition, filtered on `column` as a subquery
+    pub sql: String,
+    /// Timestamp column, or expression, the windows apply to
+    pub column: String,
+    pub range: Range<SystemTime>,
+    pub partitions: usize,
+    /// Windows run at once
+    pub max_parallel: usize,
+    /// Settings applied to every window's query, its SQL is replaced
+    pub query: Query,
+    /// Re-runs of windows that failed, without re-running the others
+    pub retry_policy: RunRetryPolicy,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum PartitionError {
+    #[error("partition {index} failed")]
+    QueryRunError {
+        index: usize,
+        #[source]
+        error: QueryRunError,
+    },
+    #[error("failed to fetch the results of partition {index}")]
+    RpcError {
+        index: usize,
+        #[source]
+        error: ApiError,
+    },
+    #[error("partition {index} has columns {found:?} instead of {expected:?}")]
+    SchemaMismatch {
+        index: usize,
+        expected: Vec<String>,
+        found: Vec<String>,
+    },
+}
+
+impl TimePartitioner {
+    pub fn new(
+        sql: impl Into<String>,
+        column: impl Into<String>,
+        range: Range<SystemTime>,
+        partitions: usize,
+    ) -> Self {
+        Self {
+            sql: sql.into(),
+            column: column.into(),
+            range,
+            partitions: partitions.max(1),
+            max_parallel: 4,
+            query: Query::default(),
+            retry_policy: RunRetryPolicy::default(),
+        }
+    }
+
+    pub fn max_parallel(mut self, max_parallel: usize) -> Self {
+        self.max_parallel = max_parallel.max(1);
+        self
+    }
+
+    pub fn query(mut self, query: Query) -> Self {
+        self.query = query;
+        self
+    }
+
+    pub fn retry_policy(mut self, retry_policy: RunRetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Contiguous windows covering the range, start inclusive and end exclusive,
+    /// aligned on milliseconds
+    pub fn windows(&self) -> Vec<Range<SystemTime>> {
+        let start = unix_millis(self.range.start);
+        let end = unix_millis(self.range.end).max(start);
+        let bound = |i: usize| {
+            let offset = (end - start) * i as u128 / self.partitions as u128;
+            UNIX_EPOCH + Duration::from_millis((start + offset) as u64)
+        };
+
+        (0..self.partitions)
+            .map(|i| bound(i)..bound(i + 1))
+            .collect()
+    }
+
+    pub fn render(&self, window: &Range<SystemTime>) -> String {
+        format!(
+            "select * from ({}) where {} >= to_timestamp_ntz({}, 3) and {} < to_timestamp_ntz({}, 3)",
+            self.sql.trim().trim_end_matches(';'),
+            self.column,
+            unix_millis(window.start),
+            self.column,
+            unix_millis(window.end),
+        )
+    }
+
+    /// Runs every window, retrying failed ones, and fails with the first window
+    /// that still failed
+    pub async fn run(&self, flipside: &Flipside) -> Result<Vec<QueryRun>, PartitionError> {
+        let queries = self
+            .windows()
+            .iter()
+            .map(|window| Query {
+                sql: self.render(window),
+                ..self.query.clone()
+            })
+            .collect();
+
+        flipside
+            .run_many_with_retry(queries, self.max_parallel, self.retry_policy)
+            .await
+            .into_iter()
+            .enumerate()
+            .map(|(index, res)| res.map_err(|error| PartitionError::QueryRunError { index, error }))
+            .collect()
+    }
+
+    /// Runs every window and merges their results
+    pub async fn run_and_collect(&self, flipside: &Flipside) -> Result<ResultSet, PartitionError> {
+        let query_runs = self.run(flipside).await?;
+        let mut merged: Option<ResultSet> = None;
+
+        for (index, query_run) in query_runs.iter().enumerate() {
+            let results = flipside
+                .collect_results(&query_run.id, None)
+                .await
+                .map_err(|error| PartitionError::RpcError { index, error })?;
+
+            match &mut merged {
+                None => merged = Some(results),
+                // Empty windows may not report columns
+                Some(_) if results.rows.is_empty() => {}
+                Some(merged) if merged.rows.is_empty() => *merged = results,
+                Some(merged) => {
+                    check_columns(index, &merged.column_names, &results.column_names)?;
+                    merged.rows.extend(results.rows);
+                }
+            }
+        }
+
+        Ok(merged.unwrap_or_default())
+    }
+
+    /// Runs every window, then streams their rows in window order, one page at a
+    /// time
+    pub async fn run_and_stream<'a>(
+        &self,
+        flipside: &'a Flipside,
+    ) -> Result<impl Stream<Item = Result<Row, PartitionError>> + 'a, PartitionError> {
+        let query_runs = self.run(flipside).await?;
+        let mut expected: Option<Vec<String>> = None;
+
+        Ok(stream::iter(query_runs.into_iter().enumerate())
+            .flat_map(move |(index, query_run)| {
+                flipside
+                    .result_pages(query_run.id, PAGE_SIZE)
+                    .map(move |page| (index, page))
+            })
+            .map(move |(index, page)| {
+                let page = ResultSet::from(
+                    page.map_err(|error| PartitionError::RpcError { index, error })?,
+                );
+                match &expected {
+                    _ if page.rows.is_empty() => {}
+                    None => expected = Some(page.column_names),
+                    Some(expected) => check_columns(index, expected, &page.column_names)?,
+                }
+                Ok(stream::iter(page.rows.into_iter().map(Ok)))
+            })
+            .try_flatten())
+    }
+}
+
+fn check_columns(
+    index: usize,
+    expected: &[String],
+    found: &[String],
+) -> Result<(), PartitionError> {
+    if expected == found {
+        return Ok(());
+    }
+    Err(PartitionError::SchemaMismatch {
+        index,
+        expected: expected.to_vec(),
+        found: found.to_vec(),
+    })
+}
+
+fn unix_millis(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(millis: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn windows_are_contiguous_and_end_on_the_range_end() {
+        let partitioner =
+            TimePartitioner::new("select 1", "block_timestamp", at(1000)..at(1010), 3);
+        assert_eq!(
+            partitioner.windows(),
+            [at(1000)..at(1003), at(1003)..at(1006), at(1006)..at(1010)]
+        );
+
+        let narrow = TimePartitioner::new("", "t", at(0)..at(2), 4);
+        assert_eq!(
+            narrow.windows(),
+            [at(0)..at(0), at(0)..at(1), at(1)..at(1), at(1)..at(2)]
+        );
+
+        let reversed = TimePartitioner::new("", "t", at(5)..at(1), 2);
+        assert_eq!(reversed.windows(), [at(5)..at(5), at(5)..at(5)]);
+
+        let unaligned = UNIX_EPOCH + Duration::from_micros(1500);
+        let partitioner = TimePartitioner::new("", "t", unaligned..at(3), 1);
+        assert_eq!(partitioner.windows(), [at(1)..at(3)]);
+    }
+
+    #[test]
+    fn render_filters_the_subquery_on_the_window() {
+        let partitioner =
+            TimePartitioner::new("select * from t;\n", "block_timestamp", at(0)..at(1), 1);
+        assert_eq!(
+            partitioner.render(&(at(1_700_000_000_000)..at(1_700_000_086_400))),
+            "select * from (select * from t) where block_timestamp >= to_timestamp_ntz(1700000000000, 3) \
+             and block_timestamp < to_timestamp_ntz(1700000086400, 3)"
+        );
+    }
+}
@@ -0,0 +1,188 @@
+use crate::result_set::{value_key, ResultSet};
+use crate::rpc::ColumnType;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+
+pub(crate) fn pivot(
+    result_set: &ResultSet,
+    index_column: &str,
+    key_column: &str,
+    value_column: &str,
+) -> Option<ResultSet> {
+    let index = result_set.column_index(index_column)?;
+    let key = result_set.column_index(key_column)?;
+    let value = result_set.column_index(value_column)?;
+
+    let mut keys: Vec<String> = Vec::new();
+    let mut used_names = HashSet::from([result_set.column_names[index].clone()]);
+    let mut key_positions = HashMap::new();
+    let mut index_positions = HashMap::new();
+    let mut rows: Vec<Vec<Value>> = Vec::new();
+
+    for row in &result_set.rows {
+        let get = |i: usize| row.get(i).cloned().unwrap_or(Value::Null);
+
+        // Keys are told apart by type, so `1` and `"1"` get their own columns
+        let key_value = get(key);
+        let column = *key_positions
+            .entry(key_value.to_string())
+            .or_insert_with(|| {
+                let name = value_key(&key_value);
+                let name = (1..)
+                    .map(|n| match n {
+                        1 => name.clone(),
+                        n => format!("{name}_{n}"),
+                    })
+                    .find(|name| used_names.insert(name.clone()))
+                    .unwrap();
+                keys.push(name);
+                keys.len()
+            });
+
+        let index_value = get(index);
+        let position = *index_positions
+            .entry(index_value.to_string())
+            .or_insert_with(|| {
+                rows.push(vec![index_value]);
+                rows.len() - 1
+            });
+
+        let row = &mut rows[position];
+        if row.len() <= column {
+            row.resize(column + 1, Value::Null);
+        }
+        row[column] = get(value);
+    }
+
+    for row in &mut rows {
+        row.resize(keys.len() + 1, Value::Null);
+    }
+
+    let column_type = |i: usize| {
+        result_set
+            .column_types
+            .get(i)
+            .cloned()
+            .unwrap_or(ColumnType::Unknown)
+    };
+
+    let mut column_names = vec![result_set.column_names[index].clone()];
+    column_names.extend(keys.iter().cloned());
+    let mut column_types = vec![column_type(index)];
+    column_types.extend(keys.iter().map(|_| column_type(value)));
+
+    Some(ResultSet {
+        column_names,
+        column_types,
+        rows,
+    })
+}
+
+pub(crate) fn unpivot(
+    result_set: &ResultSet,
+    index_columns: &[&str],
+    key_name: &str,
+    value_name: &str,
+) -> Option<ResultSet> {
+    let indices = index_columns
+        .iter()
+        .map(|column| result_set.column_index(column))
+        .collect::<Option<Vec<_>>>()?;
+    let values = (0..result_set.column_names.len())
+        .filter(|i| !indices.contains(i))
+        .collect::<Vec<_>>();
+
+    let column_type = |i: usize| {
+        result_set
+            .column_types
+            .get(i)
+            .cloned()
+            .unwrap_or(ColumnType::Unknown)
+    };
+    let value_type = match values.first() {
+        Some(&first) if values.iter().all(|&i| column_type(i) == column_type(first)) => {
+            column_type(first)
+        }
+        _ => ColumnType::Unknown,
+    };
+
+    let mut column_names = indices
+        .iter()
+        .map(|&i| result_set.column_names[i].clone())
+        .collect::<Vec<_>>();
+    column_names.push(key_name.to_string());
+    column_names.push(value_name.to_string());
+
+    let mut column_types = indices.iter().map(|&i| column_type(i)).collect::<Vec<_>>();
+    column_types.push(ColumnType::String);
+    column_types.push(value_type);
+
+    let mut rows = Vec::with_capacity(result_set.rows.len() * values.len());
+    for row in &result_set.rows {
+        let get = |i: usize| row.get(i).cloned().unwrap_or(Value::Null);
+        for &i in &values {
+            let mut unpivoted = indices.iter().map(|&j| get(j)).collect::<Vec<_>>();
+            unpivoted.push(Value::String(result_set.column_names[i].clone()));
+            unpivoted.push(get(i));
+            rows.push(unpivoted);
+        }
+    }
+
+    Some(ResultSet {
+        column_names,
+        column_types,
+        rows,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn balances(rows: Vec<[Value; 3]>) -> ResultSet {
+        ResultSet {
+            column_names: ["day", "token", "balance"].map(String::from).to_vec(),
+            column_types: vec![ColumnType::Date, ColumnType::String, ColumnType::Number],
+            rows: rows.into_iter().map(Vec::from).collect(),
+        }
+    }
+
+    #[test]
+    fn pivot_spreads_keys_into_columns() {
+        let long = balances(vec![
+            [json!("d1"), json!("eth"), json!(1)],
+            [json!("d1"), json!("btc"), json!(2)],
+            [json!("d2"), json!("eth"), json!(3)],
+        ]);
+        let wide = long.pivot("day", "token", "balance").unwrap();
+        assert_eq!(wide.column_names, ["day", "eth", "btc"]);
+        assert_eq!(
+            wide.column_types,
+            [ColumnType::Date, ColumnType::Number, ColumnType::Number]
+        );
+        assert_eq!(
+            wide.rows,
+            [
+                vec![json!("d1"), json!(1), json!(2)],
+                vec![json!("d2"), json!(3), Value::Null],
+            ]
+        );
+
+        let back = wide.unpivot(&["day"], "token", "balance").unwrap();
+        assert_eq!(back.rows.len(), 4);
+        assert_eq!(back.rows[1], [json!("d1"), json!("btc"), json!(2)]);
+    }
+
+    #[test]
+    fn colliding_column_names_are_suffixed() {
+        let long = balances(vec![
+            [json!("d1"), json!(1), json!(1)],
+            [json!("d1"), json!("1"), json!(2)],
+            [json!("d1"), json!("day"), json!(3)],
+        ]);
+        let wide = long.pivot("day", "token", "balance").unwrap();
+        assert_eq!(wide.column_names, ["day", "1", "1_2", "day_2"]);
+        assert_eq!(wide.rows, [vec![json!("d1"), json!(1), json!(2), json!(3)]]);
+    }
+}
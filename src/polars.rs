@@ -0,0 +1,149 @@
+use crate::error::ApiError;
+use crate::flipside::{Flipside, Query, QueryRunError};
+use crate::result_set::{as_integer, ResultSet};
+use crate::rpc::ColumnType;
+use crate::stats::parse_timestamp;
+use polars::prelude::{Column, DataFrame, DataType, PolarsError, TimeUnit};
+use serde_json::Value;
+
+#[derive(Debug, thiserror::Error)]
+pub enum DataFrameError {
+    #[error(transparent)]
+    QueryRunError(#[from] QueryRunError),
+    #[error(transparent)]
+    RpcError(#[from] ApiError),
+    #[error(transparent)]
+    PolarsError(#[from] PolarsError),
+}
+
+/// Polars type of a result column. Numbers are `Float64`, dates UTC millisecond
+/// datetimes, and objects, arrays and unknown types JSON text. `to_dataframe`
+/// makes number columns holding only integers `Int64` instead.
+pub fn dtype(column_type: &ColumnType) -> DataType {
+    match column_type {
+        ColumnType::Number => DataType::Float64,
+        ColumnType::Boolean => DataType::Boolean,
+        ColumnType::Date => DataType::Datetime(TimeUnit::Milliseconds, Some("UTC".into())),
+        ColumnType::String | ColumnType::Object | ColumnType::Array | ColumnType::Unknown => {
+            DataType::String
+        }
+    }
+}
+
+impl ResultSet {
+    /// Converts the rows to a data frame typed from `column_types`, with number
+    /// columns holding only integers as `Int64`. Values that don't fit their
+    /// column's type become nulls.
+    pub fn to_dataframe(&self) -> Result<DataFrame, PolarsError> {
+        let columns = self
+            .column_names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                let column_type = self.column_types.get(i).unwrap_or(&ColumnType::Unknown);
+                let values = self
+                    .rows
+                    .iter()
+                    .map(|row| row.get(i).unwrap_or(&Value::Null));
+                if *column_type == ColumnType::Number && self.is_integral(i) {
+                    Ok(Column::new(
+                        name.into(),
+                        values.map(as_integer).collect::<Vec<_>>(),
+                    ))
+                } else {
+                    column(name, values, column_type)
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        DataFrame::new(columns)
+    }
+}
+
+fn column<'a>(
+    name: &str,
+    values: impl Iterator<Item = &'a Value>,
+    column_type: &ColumnType,
+) -> Result<Column, PolarsError> {
+    let name = name.into();
+    Ok(match column_type {
+        ColumnType::Number => Column::new(
+            name,
+            values
+                .map(|value| match value {
+                    Value::Number(n) => n.as_f64(),
+                    Value::String(s) => s.trim().parse().ok(),
+                    _ => None,
+                })
+                .collect::<Vec<_>>(),
+        ),
+        ColumnType::Boolean => Column::new(
+            name,
+            values
+                .map(|value| match value {
+                    Value::Bool(b) => Some(*b),
+                    Value::String(s) => s.to_lowercase().parse().ok(),
+                    _ => None,
+                })
+                .collect::<Vec<_>>(),
+        ),
+        ColumnType::Date => Column::new(
+            name,
+            values
+                .map(|value| {
+                    let seconds = parse_timestamp(value.as_str()?)?;
+                    Some((seconds * 1000.0).round() as i64)
+                })
+                .collect::<Vec<_>>(),
+        )
+        .cast(&dtype(column_type))?,
+        _ => Column::new(
+            name,
+            values
+                .map(|value| match value {
+                    Value::Null => None,
+                    Value::String(s) => Some(s.clone()),
+                    value => Some(value.to_string()),
+                })
+                .collect::<Vec<_>>(),
+        ),
+    })
+}
+
+impl Flipside {
+    /// Runs `sql` and fetches all of its results as a data frame
+    pub async fn query_df(&self, sql: impl Into<String>) -> Result<DataFrame, DataFrameError> {
+        let query_run = self.run(Query::new(sql)).await?;
+        Ok(self
+            .collect_results(&query_run.id, None)
+            .await?
+            .to_dataframe()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn integral_number_columns_are_int64() {
+        let result_set = ResultSet {
+            column_names: vec!["id".to_string(), "amount".to_string()],
+            column_types: vec![ColumnType::Number, ColumnType::Number],
+            rows: vec![
+                vec![json!(9007199254740993i64), json!(1)],
+                vec![json!("2"), json!(1.5)],
+                vec![Value::Null, Value::Null],
+            ],
+        };
+        let df = result_set.to_dataframe().unwrap();
+        let id = df.column("id").unwrap();
+        assert_eq!(id.dtype(), &DataType::Int64);
+        assert_eq!(
+            id.i64().unwrap().into_iter().collect::<Vec<_>>(),
+            [Some(9007199254740993), Some(2), None]
+        );
+        assert_eq!(df.column("amount").unwrap().dtype(), &DataType::Float64);
+    }
+}
@@ -0,0 +1,144 @@
+use std::collections::hash_map::RandomState;
+use std::fmt::Debug;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+/// How long to wait between polls of a query run
+pub trait PollStrategy: Debug + Send + Sync {
+    /// Delay before the poll following the `attempt`th one, starting at 0
+    fn interval(&self, attempt: u32) -> Duration;
+
+    /// Never waits longer than `max_interval`
+    fn capped(self, max_interval: Duration) -> Capped<Self>
+    where
+        Self: Sized,
+    {
+        Capped {
+            strategy: self,
+            max_interval,
+        }
+    }
+
+    /// Randomly shortens or lengthens each interval by up to `ratio` of it (0 to 1),
+    /// so many clients polling at once spread out
+    fn with_jitter(self, ratio: f64) -> Jittered<Self>
+    where
+        Self: Sized,
+    {
+        Jittered {
+            strategy: self,
+            ratio: ratio.clamp(0.0, 1.0),
+        }
+    }
+}
+
+/// `base`, `2 * base`, `3 * base`...
+#[derive(Debug, Clone, Copy)]
+pub struct Linear(pub Duration);
+
+impl PollStrategy for Linear {
+    fn interval(&self, attempt: u32) -> Duration {
+        self.0.saturating_mul(attempt.saturating_add(1))
+    }
+}
+
+/// `base`, `base * factor`, `base * factor^2`...
+#[derive(Debug, Clone, Copy)]
+pub struct Exponential {
+    pub base: Duration,
+    pub factor: f64,
+}
+
+impl PollStrategy for Exponential {
+    fn interval(&self, attempt: u32) -> Duration {
+        let secs = self.base.as_secs_f64() * self.factor.powi(attempt.min(i32::MAX as u32) as i32);
+        Duration::try_from_secs_f64(secs).unwrap_or(Duration::MAX)
+    }
+}
+
+/// `base`, `base`, `2 * base`, `3 * base`, `5 * base`...
+#[derive(Debug, Clone, Copy)]
+pub struct Fibonacci(pub Duration);
+
+impl PollStrategy for Fibonacci {
+    fn interval(&self, attempt: u32) -> Duration {
+        let (mut a, mut b) = (1u32, 1u32);
+        for _ in 0..attempt {
+            (a, b) = (b, a.saturating_add(b));
+        }
+        self.0.saturating_mul(a)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Capped<S> {
+    pub strategy: S,
+    pub max_interval: Duration,
+}
+
+impl<S: PollStrategy> PollStrategy for Capped<S> {
+    fn interval(&self, attempt: u32) -> Duration {
+        self.strategy.interval(attempt).min(self.max_interval)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Jittered<S> {
+    pub strategy: S,
+    pub ratio: f64,
+}
+
+impl<S: PollStrategy> PollStrategy for Jittered<S> {
+    fn interval(&self, attempt: u32) -> Duration {
+        let interval = self.strategy.interval(attempt);
+        // `RandomState` is randomly keyed, so hashing nothing yields a random number
+        let random = RandomState::new().build_hasher().finish() as f64 / u64::MAX as f64;
+        let factor = (1.0 + self.ratio * (2.0 * random - 1.0)).max(0.0);
+        Duration::try_from_secs_f64(interval.as_secs_f64() * factor).unwrap_or(Duration::MAX)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn built_in_strategies() {
+        let base = Duration::from_secs(1);
+        let secs = |strategy: &dyn PollStrategy| {
+            (0..5)
+                .map(|attempt| strategy.interval(attempt).as_secs())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(secs(&Linear(base)), [1, 2, 3, 4, 5]);
+        assert_eq!(secs(&Exponential { base, factor: 2.0 }), [1, 2, 4, 8, 16]);
+        assert_eq!(secs(&Fibonacci(base)), [1, 1, 2, 3, 5]);
+        assert_eq!(
+            secs(&Linear(base).capped(Duration::from_secs(3))),
+            [1, 2, 3, 3, 3]
+        );
+    }
+
+    #[test]
+    fn jitter_stays_within_ratio_and_never_overflows() {
+        let jittered = Linear(Duration::from_secs(10)).with_jitter(0.5);
+        for _ in 0..100 {
+            let interval = jittered.interval(0);
+            assert!((Duration::from_secs(5)..=Duration::from_secs(15)).contains(&interval));
+        }
+
+        let unbounded = Exponential {
+            base: Duration::from_secs(1),
+            factor: 10.0,
+        };
+        assert_eq!(unbounded.interval(u32::MAX), Duration::MAX);
+        let jittered = Jittered {
+            strategy: unbounded,
+            ratio: 2.0,
+        };
+        for _ in 0..100 {
+            jittered.interval(u32::MAX);
+        }
+    }
+}
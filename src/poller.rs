@@ -0,0 +1,112 @@
+use crate::flipside::Flipside;
+use crate::rpc::QueryRun;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::watch;
+
+/// Consecutive failed batch requests after which subscribers are sent `None`, so
+/// they poll their runs themselves and see the error
+const MAX_BATCH_FAILURES: u32 = 3;
+
+/// Polls every run being waited on through a client on a single schedule, with
+/// one batch request per tick. Subscribers receive `None` if their run couldn't be
+/// fetched, or batch requests keep failing, and should poll it themselves.
+#[derive(Debug, Clone)]
+pub(crate) struct SharedPoller {
+    interval: Duration,
+    state: Arc<Mutex<PollerState>>,
+}
+
+#[derive(Debug, Default)]
+struct PollerState {
+    runs: HashMap<String, watch::Sender<Option<QueryRun>>>,
+    running: bool,
+}
+
+impl SharedPoller {
+    pub(crate) fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            state: Arc::default(),
+        }
+    }
+
+    /// Receiver updated every tick until the run is terminal. The polling task is
+    /// started on the first subscription and stops once nobody is subscribed.
+    pub(crate) fn subscribe(
+        &self,
+        flipside: &Flipside,
+        query_run: &QueryRun,
+    ) -> watch::Receiver<Option<QueryRun>> {
+        let mut state = self.state.lock().unwrap();
+        let rx = state
+            .runs
+            .entry(query_run.id.clone())
+            .or_insert_with(|| watch::channel(Some(query_run.clone())).0)
+            .subscribe();
+
+        if !state.running {
+            state.running = true;
+            tokio::spawn(self.clone().run(flipside.clone()));
+        }
+        rx
+    }
+
+    async fn run(self, flipside: Flipside) {
+        let mut failures = 0;
+        loop {
+            tokio::time::sleep(self.interval).await;
+
+            let query_run_ids = {
+                let mut state = self.state.lock().unwrap();
+                state.runs.retain(|_, tx| tx.receiver_count() > 0);
+                if state.runs.is_empty() {
+                    state.running = false;
+                    return;
+                }
+                state.runs.keys().cloned().collect::<Vec<_>>()
+            };
+
+            let query_runs = match flipside.get_query_runs(query_run_ids.clone()).await {
+                Ok(query_runs) => {
+                    failures = 0;
+                    query_runs
+                }
+                Err(_) => {
+                    failures += 1;
+                    if failures >= MAX_BATCH_FAILURES {
+                        failures = 0;
+                        let mut state = self.state.lock().unwrap();
+                        for query_run_id in &query_run_ids {
+                            if let Some(tx) = state.runs.remove(query_run_id) {
+                                tx.send_replace(None);
+                            }
+                        }
+                    }
+                    continue;
+                }
+            };
+
+            let mut state = self.state.lock().unwrap();
+            for (query_run_id, query_run) in query_run_ids.into_iter().zip(query_runs) {
+                match query_run {
+                    Ok(query_run) => {
+                        let terminal = query_run.state.is_terminal();
+                        if let Some(tx) = state.runs.get(&query_run_id) {
+                            tx.send_replace(Some(query_run));
+                        }
+                        if terminal {
+                            state.runs.remove(&query_run_id);
+                        }
+                    }
+                    Err(_) => {
+                        if let Some(tx) = state.runs.remove(&query_run_id) {
+                            tx.send_replace(None);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
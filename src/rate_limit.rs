@@ -0,0 +1,119 @@
+use crate::error::InvalidRate;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Lowest accepted rate, one call a day, in calls per second. Lower rates are
+/// almost certainly mistakes, and their waits approach what `Duration` can hold.
+pub(crate) const MIN_RATE: f64 = 1.0 / 86_400.0;
+
+/// Token bucket holding up to `capacity` tokens, refilled at `rate` tokens per
+/// second
+#[derive(Debug)]
+struct TokenBucket {
+    capacity: f64,
+    rate: f64,
+    state: Mutex<(f64, Instant)>,
+}
+
+impl TokenBucket {
+    fn new(capacity: f64, rate: f64) -> Self {
+        Self {
+            capacity,
+            rate,
+            state: Mutex::new((capacity, Instant::now())),
+        }
+    }
+
+    /// Waits until a token is available and takes it
+    async fn acquire(&self) {
+        loop {
+            let wait = {
+                let mut state = self.state.lock().unwrap();
+                let (tokens, refilled_at) = &mut *state;
+                let now = Instant::now();
+                *tokens = (*tokens + now.duration_since(*refilled_at).as_secs_f64() * self.rate)
+                    .min(self.capacity);
+                *refilled_at = now;
+
+                if *tokens >= 1.0 {
+                    *tokens -= 1.0;
+                    return;
+                }
+                Duration::try_from_secs_f64((1.0 - *tokens) / self.rate).unwrap_or(Duration::MAX)
+            };
+
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// Client-side limits on the rate of API calls, shared by clones of a client
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RateLimiter {
+    requests: Option<Arc<TokenBucket>>,
+    queries: Option<Arc<TokenBucket>>,
+}
+
+impl RateLimiter {
+    /// Limits every RPC call, allowing bursts of up to one second's worth
+    pub(crate) fn set_requests_per_second(
+        &mut self,
+        requests_per_second: f64,
+    ) -> Result<(), InvalidRate> {
+        if !requests_per_second.is_finite() || requests_per_second < MIN_RATE {
+            return Err(InvalidRate(requests_per_second));
+        }
+        self.requests = Some(Arc::new(TokenBucket::new(
+            requests_per_second.max(1.0),
+            requests_per_second,
+        )));
+        Ok(())
+    }
+
+    /// Limits the creation of query runs, allowing bursts of up to one minute's
+    /// worth
+    pub(crate) fn set_queries_per_minute(
+        &mut self,
+        queries_per_minute: u32,
+    ) -> Result<(), InvalidRate> {
+        if queries_per_minute == 0 {
+            return Err(InvalidRate(0.0));
+        }
+        self.queries = Some(Arc::new(TokenBucket::new(
+            queries_per_minute.into(),
+            f64::from(queries_per_minute) / 60.0,
+        )));
+        Ok(())
+    }
+
+    pub(crate) async fn acquire_request(&self) {
+        if let Some(bucket) = &self.requests {
+            bucket.acquire().await;
+        }
+    }
+
+    pub(crate) async fn acquire_query(&self) {
+        if let Some(bucket) = &self.queries {
+            bucket.acquire().await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rates_must_be_positive_and_finite() {
+        let mut limiter = RateLimiter::default();
+        for rate in [0.0, -1.0, 1e-20, f64::NAN, f64::INFINITY] {
+            assert!(limiter.set_requests_per_second(rate).is_err());
+        }
+        assert!(limiter.set_queries_per_minute(0).is_err());
+        assert!(limiter.requests.is_none() && limiter.queries.is_none());
+
+        assert_eq!(limiter.set_requests_per_second(0.5), Ok(()));
+        assert_eq!(limiter.set_requests_per_second(MIN_RATE), Ok(()));
+        assert_eq!(limiter.set_queries_per_minute(10), Ok(()));
+    }
+}
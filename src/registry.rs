@@ -0,0 +1,642 @@
+use crate::error::ApiError;
+use crate::flipside::{CachePolicy, DataProvider, DataSource, Flipside, Query, QueryRunError};
+use crate::result_set::{Fnv64, ResultSet};
+use crate::rpc::QueryRun;
+use crate::sql::{quote_literal, replace_placeholders};
+use crate::tags::{TagError, Tags};
+use flipside_sql::split_front_matter;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+pub const MANIFEST_FILE: &str = "queries.toml";
+pub const QUERY_NAME_TAG: &str = "query_name";
+pub const QUERY_VERSION_TAG: &str = "query_version";
+pub const QUERY_HASH_TAG: &str = "query_hash";
+
+/// A named query from the registry manifest
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct QueryDefinition {
+    /// SQL file relative to the registry directory, defaults to `<name>.sql`
+    pub file: Option<String>,
+    #[serde(skip)]
+    pub sql: String,
+    pub data_source: Option<DataSource>,
+    pub data_provider: Option<DataProvider>,
+    pub max_age_minutes: Option<u64>,
+    pub timeout_seconds: Option<u64>,
+    #[serde(default)]
+    pub tags: HashMap<String, String>,
+    /// Default values for the `:param` placeholders in the SQL
+    #[serde(default)]
+    pub params: HashMap<String, String>,
+    /// Declared parameter types, enforced by `run_named`
+    #[serde(default)]
+    pub param_types: HashMap<String, ParamType>,
+    /// When the query is meant to run, e.g. a cron expression. Not interpreted by the SDK.
+    pub schedule: Option<String>,
+    /// Current version of the query, stamped on its runs along with the SQL hash
+    pub version: Option<String>,
+    /// History of changes to the query, most recent last
+    #[serde(default)]
+    pub changelog: Vec<ChangelogEntry>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ChangelogEntry {
+    pub version: String,
+    pub date: Option<String>,
+    pub note: Option<String>,
+}
+
+/// Which registered query, and which version of it, a run was created from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryVersion {
+    pub name: String,
+    pub version: Option<String>,
+    pub hash: String,
+}
+
+impl QueryVersion {
+    /// Reads the version tags stamped by the registry, if the run has them
+    pub fn of(query_run: &QueryRun) -> Option<Self> {
+        Some(Self {
+            name: query_run.tags.get(QUERY_NAME_TAG)?.to_string(),
+            version: query_run.tags.get(QUERY_VERSION_TAG).map(String::from),
+            hash: query_run.tags.get(QUERY_HASH_TAG)?.to_string(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ParamType {
+    String,
+    Number,
+    Integer,
+    Boolean,
+    /// `YYYY-MM-DD`, optionally followed by a time
+    Date,
+}
+
+impl ParamType {
+    /// Checks that `value` has this type and renders it as a SQL literal.
+    /// Non-string types also accept their string representation.
+    pub fn bind(&self, value: &Value) -> Result<String, String> {
+        match (self, value) {
+            (ParamType::String, Value::String(s)) => Ok(quote_literal(s)),
+            (ParamType::Number, Value::Number(n)) => Ok(n.to_string()),
+            (ParamType::Number, Value::String(s))
+                if s.trim().parse::<f64>().is_ok_and(f64::is_finite) =>
+            {
+                Ok(s.trim().to_string())
+            }
+            (ParamType::Integer, Value::Number(n)) if n.is_i64() || n.is_u64() => Ok(n.to_string()),
+            (ParamType::Integer, Value::String(s)) if s.trim().parse::<i128>().is_ok() => {
+                Ok(s.trim().to_string())
+            }
+            (ParamType::Boolean, Value::Bool(b)) => Ok(b.to_string().to_uppercase()),
+            (ParamType::Boolean, Value::String(s)) if s == "true" || s == "false" => {
+                Ok(s.to_uppercase())
+            }
+            (ParamType::Date, Value::String(s)) if is_date(s) => Ok(quote_literal(s)),
+            (param_type, value) => Err(format!("expected {:?}, got {}", param_type, value)),
+        }
+    }
+}
+
+fn is_date(s: &str) -> bool {
+    let bytes = s.as_bytes();
+    bytes.len() >= 10
+        && bytes[..10].iter().enumerate().all(|(i, b)| {
+            if i == 4 || i == 7 {
+                *b == b'-'
+            } else {
+                b.is_ascii_digit()
+            }
+        })
+        && s[10..]
+            .chars()
+            .all(|c| c.is_ascii_digit() || " T:.+-Z".contains(c))
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RegistryError {
+    #[error("failed to read the registry")]
+    IoError(#[source] io::Error),
+    #[error("invalid registry manifest")]
+    ManifestError(#[source] toml::de::Error),
+    #[error("unknown query `{0}`")]
+    UnknownQuery(String),
+    #[error("query `{query}` is missing parameter `{param}`")]
+    MissingParam { query: String, param: String },
+    #[error("invalid parameter `{param}` for query `{query}`: {reason}")]
+    InvalidParam {
+        query: String,
+        param: String,
+        reason: String,
+    },
+    /// Queries that reference each other through `{{ ref(...) }}`
+    #[error("queries reference each other: {}", .0.join(" -> "))]
+    RefCycle(Vec<String>),
+    #[error(transparent)]
+    InvalidTag(#[from] TagError),
+    #[error(transparent)]
+    QueryRunError(#[from] QueryRunError),
+    #[error(transparent)]
+    RpcError(#[from] ApiError),
+}
+
+/// Named queries loaded from a directory containing a `queries.toml` manifest and
+/// the `.sql` files it refers to.
+///
+/// ```toml
+/// [daily_active_users]
+/// file = "dau.sql"
+/// max_age_minutes = 60
+/// tags = { team = "growth" }
+/// version = "2"
+/// changelog = [{ version = "2", date = "2025-01-01", note = "exclude bots" }]
+/// params = { chain = "ethereum" }
+/// schedule = "0 6 * * *"
+/// ```
+///
+/// Without a manifest, every `.sql` file of the directory is a query named after
+/// the file, configured by optional TOML front-matter taking the same keys.
+/// `include_sql!` skips the front-matter, so the same file can be embedded too:
+///
+/// ```sql
+/// ---
+/// data_source = "snowflake-default"
+/// tags = { team = "growth" }
+/// ---
+/// select * from ethereum.core.fact_transactions where block_number > :from_block
+/// ```
+///
+/// Parameters are `:name` placeholders, as in `Query::bind`.
+#[derive(Debug, Clone, Default)]
+pub struct QueryRegistry {
+    queries: HashMap<String, QueryDefinition>,
+}
+
+impl QueryRegistry {
+    pub fn load(dir: impl AsRef<Path>) -> Result<Self, RegistryError> {
+        let dir = dir.as_ref();
+        if !dir.join(MANIFEST_FILE).exists() {
+            return Self::from_sql_files(dir);
+        }
+        Self::from_files(|file| fs::read_to_string(dir.join(file)))
+    }
+
+    fn from_sql_files(dir: &Path) -> Result<Self, RegistryError> {
+        let mut queries = HashMap::new();
+
+        for entry in fs::read_dir(dir).map_err(RegistryError::IoError)? {
+            let path = entry.map_err(RegistryError::IoError)?.path();
+            let Some(name) = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .filter(|_| path.extension().is_some_and(|ext| ext == "sql"))
+            else {
+                continue;
+            };
+
+            let contents = fs::read_to_string(&path).map_err(RegistryError::IoError)?;
+            queries.insert(name.to_string(), parse_front_matter(&contents)?);
+        }
+
+        Ok(Self { queries })
+    }
+
+    /// Loads a registry embedded in the binary, see `embed_queries!`
+    pub fn from_embedded(dir: &include_dir::Dir<'_>) -> Result<Self, RegistryError> {
+        Self::from_files(|file| {
+            dir.get_file(file)
+                .and_then(|file| file.contents_utf8())
+                .map(String::from)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, file.to_string()))
+        })
+    }
+
+    fn from_files(read: impl Fn(&str) -> io::Result<String>) -> Result<Self, RegistryError> {
+        let manifest = read(MANIFEST_FILE).map_err(RegistryError::IoError)?;
+        let mut queries: HashMap<String, QueryDefinition> =
+            toml::from_str(&manifest).map_err(RegistryError::ManifestError)?;
+
+        for (name, definition) in &mut queries {
+            let file = definition
+                .file
+                .clone()
+                .unwrap_or_else(|| format!("{}.sql", name));
+            definition.sql = read(&file).map_err(RegistryError::IoError)?;
+        }
+
+        Ok(Self { queries })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&QueryDefinition> {
+        self.queries.get(name)
+    }
+
+    /// Changelog entry for the version that produced `query_run`, if the run came
+    /// from this registry and its version is listed
+    pub fn changelog_entry(&self, query_run: &QueryRun) -> Option<&ChangelogEntry> {
+        let version = QueryVersion::of(query_run)?;
+        self.get(&version.name)?
+            .changelog
+            .iter()
+            .find(|entry| Some(&entry.version) == version.version.as_ref())
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.queries.keys().map(String::as_str)
+    }
+
+    /// SQL of `name` with every `{{ ref("other") }}` directive replaced by the
+    /// referenced query, recursively, as a parenthesized subquery
+    pub fn resolve_refs(&self, name: &str) -> Result<String, RegistryError> {
+        self.resolve_refs_inner(name, &mut Vec::new())
+    }
+
+    fn resolve_refs_inner(
+        &self,
+        name: &str,
+        stack: &mut Vec<String>,
+    ) -> Result<String, RegistryError> {
+        if stack.iter().any(|entry| entry == name) {
+            stack.push(name.to_string());
+            return Err(RegistryError::RefCycle(std::mem::take(stack)));
+        }
+
+        let definition = self
+            .get(name)
+            .ok_or_else(|| RegistryError::UnknownQuery(name.to_string()))?;
+
+        stack.push(name.to_string());
+        let mut out = String::with_capacity(definition.sql.len());
+        let mut rest = definition.sql.as_str();
+
+        while let Some(start) = rest.find("{{") {
+            out.push_str(&rest[..start]);
+            rest = &rest[start..];
+
+            let Some(end) = rest.find("}}") else {
+                break;
+            };
+
+            match parse_ref(&rest[2..end]) {
+                Some(other) => {
+                    let sql = self.resolve_refs_inner(other, stack)?;
+                    out.push('(');
+                    out.push_str(sql.trim().trim_end_matches(';'));
+                    out.push(')');
+                }
+                None => out.push_str(&rest[..end + 2]),
+            }
+            rest = &rest[end + 2..];
+        }
+
+        out.push_str(rest);
+        stack.pop();
+        Ok(out)
+    }
+
+    /// Stable hash of the SQL of `name` with its refs resolved, so it changes
+    /// when any query it references is edited
+    pub fn hash(&self, name: &str) -> Result<String, RegistryError> {
+        self.resolve_refs(name).map(|sql| sql_hash(&sql))
+    }
+
+    /// Builds the query for `name`, filling its placeholders from `params` and
+    /// then from the manifest defaults. Values from `params` are bound as SQL
+    /// literals of their declared type, or as string literals if undeclared.
+    /// Defaults without a declared type are inserted as written in the manifest.
+    pub fn render(
+        &self,
+        name: &str,
+        params: &HashMap<String, String>,
+    ) -> Result<Query, RegistryError> {
+        let definition = self
+            .get(name)
+            .ok_or_else(|| RegistryError::UnknownQuery(name.to_string()))?;
+
+        let mut error = None;
+        let resolved = self.resolve_refs(name)?;
+        let sql = render_template(&resolved, |param| {
+            let param_type = definition.param_types.get(param);
+            let bound = match (params.get(param), param_type) {
+                (Some(value), param_type) => param_type
+                    .unwrap_or(&ParamType::String)
+                    .bind(&Value::String(value.clone())),
+                (None, Some(param_type)) => {
+                    param_type.bind(&Value::String(definition.params.get(param)?.clone()))
+                }
+                (None, None) => return definition.params.get(param).cloned(),
+            };
+
+            bound
+                .map_err(|reason| {
+                    error = Some(RegistryError::InvalidParam {
+                        query: name.to_string(),
+                        param: param.to_string(),
+                        reason,
+                    })
+                })
+                .ok()
+        })
+        .map_err(|param| {
+            error.take().unwrap_or(RegistryError::MissingParam {
+                query: name.to_string(),
+                param,
+            })
+        })?;
+
+        definition.query(name, sql, sql_hash(&resolved))
+    }
+
+    /// Builds the query for `name`, checking every parameter against its declared
+    /// type and binding it as a SQL literal
+    pub fn render_typed(
+        &self,
+        name: &str,
+        params: &HashMap<String, Value>,
+    ) -> Result<Query, RegistryError> {
+        let definition = self
+            .get(name)
+            .ok_or_else(|| RegistryError::UnknownQuery(name.to_string()))?;
+        let invalid = |param: &str, reason: String| RegistryError::InvalidParam {
+            query: name.to_string(),
+            param: param.to_string(),
+            reason,
+        };
+
+        if let Some(param) = params
+            .keys()
+            .find(|param| !definition.param_types.contains_key(*param))
+        {
+            return Err(invalid(param, "not declared".to_string()));
+        }
+
+        let mut error = None;
+        let resolved = self.resolve_refs(name)?;
+        let sql = render_template(&resolved, |param| {
+            let Some(param_type) = definition.param_types.get(param) else {
+                error = Some(invalid(param, "not declared".to_string()));
+                return None;
+            };
+            let value = params
+                .get(param)
+                .cloned()
+                .or_else(|| definition.params.get(param).cloned().map(Value::String))?;
+
+            param_type
+                .bind(&value)
+                .map_err(|reason| error = Some(invalid(param, reason)))
+                .ok()
+        })
+        .map_err(|param| {
+            error.take().unwrap_or(RegistryError::MissingParam {
+                query: name.to_string(),
+                param,
+            })
+        })?;
+
+        definition.query(name, sql, sql_hash(&resolved))
+    }
+
+    /// Runs a query with typed parameters and fetches all of its results
+    pub async fn run_named(
+        &self,
+        flipside: &Flipside,
+        name: &str,
+        params: &HashMap<String, Value>,
+    ) -> Result<ResultSet, RegistryError> {
+        let query_run = flipside
+            .run(self.render_typed(name, params)?)
+            .await
+            .map_err(RegistryError::QueryRunError)?;
+
+        flipside
+            .collect_results(&query_run.id, None)
+            .await
+            .map_err(RegistryError::RpcError)
+    }
+
+    pub async fn run(
+        &self,
+        flipside: &Flipside,
+        name: &str,
+        params: &HashMap<String, String>,
+    ) -> Result<QueryRun, RegistryError> {
+        flipside
+            .run(self.render(name, params)?)
+            .await
+            .map_err(RegistryError::QueryRunError)
+    }
+}
+
+impl QueryDefinition {
+    /// `sql` is the rendered query and `hash` the hash of its resolved SQL
+    fn query(&self, name: &str, sql: String, hash: String) -> Result<Query, RegistryError> {
+        let mut tags = Tags::new();
+        for (key, value) in &self.tags {
+            tags.insert(key.clone(), value.clone())
+                .map_err(RegistryError::InvalidTag)?;
+        }
+        tags.insert(QUERY_NAME_TAG, name)
+            .and_then(|_| tags.insert(QUERY_HASH_TAG, hash))
+            .and_then(|_| match &self.version {
+                Some(version) => tags.insert(QUERY_VERSION_TAG, version.clone()),
+                None => Ok(()),
+            })
+            .map_err(RegistryError::InvalidTag)?;
+
+        Ok(Query {
+            sql,
+            tags,
+            cache_policy: self
+                .max_age_minutes
+                .map(|minutes| CachePolicy::UseCacheUpTo(Duration::from_secs(minutes * 60)))
+                .unwrap_or_default(),
+            timeout: self.timeout_seconds.map(Duration::from_secs),
+            data_source: self.data_source.clone(),
+            data_provider: self.data_provider.clone(),
+            ..Default::default()
+        })
+    }
+}
+
+/// Name inside a `ref("name")` or `ref('name')` directive
+fn sql_hash(sql: &str) -> String {
+    let mut hasher = Fnv64::default();
+    hasher.write(sql.as_bytes());
+    format!("{:016x}", hasher.finish())
+}
+
+fn parse_ref(directive: &str) -> Option<&str> {
+    let inner = directive
+        .trim()
+        .strip_prefix("ref(")?
+        .strip_suffix(')')?
+        .trim();
+
+    inner
+        .strip_prefix('"')
+        .and_then(|inner| inner.strip_suffix('"'))
+        .or_else(|| {
+            inner
+                .strip_prefix('\'')
+                .and_then(|inner| inner.strip_suffix('\''))
+        })
+}
+
+/// Parses a `.sql` file's TOML front-matter, if any, and its SQL
+fn parse_front_matter(contents: &str) -> Result<QueryDefinition, RegistryError> {
+    match split_front_matter(contents) {
+        (Some(front_matter), sql) => {
+            let mut definition: QueryDefinition =
+                toml::from_str(front_matter).map_err(RegistryError::ManifestError)?;
+            definition.sql = sql.to_string();
+            Ok(definition)
+        }
+        (None, sql) => Ok(QueryDefinition {
+            sql: sql.to_string(),
+            ..Default::default()
+        }),
+    }
+}
+
+/// Replaces `:name` placeholders, returning the first one `lookup` can't resolve.
+/// Placeholders are found like `Query::bind` and `include_sql!` find them, so
+/// string literals, comments, casts and semi-structured paths are left untouched.
+fn render_template(
+    sql: &str,
+    mut lookup: impl FnMut(&str) -> Option<String>,
+) -> Result<String, String> {
+    let mut missing = None;
+    let sql = replace_placeholders(sql, |name| {
+        let value = lookup(name);
+        if value.is_none() && missing.is_none() {
+            missing = Some(name.to_string());
+        }
+        value
+    });
+
+    match missing {
+        Some(name) => Err(name),
+        None => Ok(sql),
+    }
+}
+
+/// Embeds a registry directory (a `queries.toml` manifest and its `.sql` files)
+/// into the binary, evaluating to `Result<QueryRegistry, RegistryError>`.
+/// Relative paths are resolved from the compiler's working directory, so prefer
+/// `embed_queries!("$CARGO_MANIFEST_DIR/queries")`.
+#[macro_export]
+macro_rules! embed_queries {
+    ($path:tt) => {{
+        use $crate::include_dir;
+        $crate::registry::QueryRegistry::from_embedded(&include_dir::include_dir!($path))
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry() -> QueryRegistry {
+        QueryRegistry::from_files(|file| match file {
+            MANIFEST_FILE => Ok(r#"
+                [transfers]
+                params = { symbol = "ETH", limit = "100" }
+                param_types = { min_amount = "number" }
+            "#
+            .to_string()),
+            "transfers.sql" => Ok(
+                "select * from t where symbol = :symbol and amount > :min_amount limit :limit -- ':symbol'"
+                    .to_string(),
+            ),
+            file => Err(io::Error::new(io::ErrorKind::NotFound, file.to_string())),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn render_binds_caller_values_as_literals() {
+        let params = HashMap::from([
+            ("symbol".to_string(), "x' or '1'='1".to_string()),
+            ("min_amount".to_string(), "10".to_string()),
+        ]);
+        let query = registry().render("transfers", &params).unwrap();
+
+        assert_eq!(
+            query.sql,
+            "select * from t where symbol = 'x'' or ''1''=''1' and amount > 10 limit 100 -- ':symbol'"
+        );
+    }
+
+    #[test]
+    fn render_checks_declared_types() {
+        let params = HashMap::from([("min_amount".to_string(), "1; drop table t".to_string())]);
+        assert!(matches!(
+            registry().render("transfers", &params),
+            Err(RegistryError::InvalidParam { .. })
+        ));
+    }
+
+    #[test]
+    fn placeholders_in_literals_and_comments_are_left_alone() {
+        let registry = QueryRegistry {
+            queries: HashMap::from([(
+                "q".to_string(),
+                parse_front_matter(
+                    "---\nparams = { a = \"1\" }\n---\nselect ':a', x::int /* :b */ where y = :a\n",
+                )
+                .unwrap(),
+            )]),
+        };
+        let query = registry.render("q", &HashMap::new()).unwrap();
+        assert_eq!(query.sql, "select ':a', x::int /* :b */ where y = 1\n");
+
+        let missing = QueryRegistry {
+            queries: HashMap::from([("q".to_string(), parse_front_matter("select :b").unwrap())]),
+        };
+        assert!(matches!(
+            missing.render("q", &HashMap::new()),
+            Err(RegistryError::MissingParam { param, .. }) if param == "b"
+        ));
+    }
+
+    #[test]
+    fn hash_covers_referenced_queries() {
+        let registry = |prices: &str| QueryRegistry {
+            queries: HashMap::from([
+                ("prices".to_string(), parse_front_matter(prices).unwrap()),
+                (
+                    "volume".to_string(),
+                    parse_front_matter("select * from {{ ref(\"prices\") }} p").unwrap(),
+                ),
+            ]),
+        };
+        let before = registry("select * from prices");
+        let after = registry("select * from prices where symbol = 'ETH'");
+
+        assert_ne!(
+            before.hash("volume").unwrap(),
+            after.hash("volume").unwrap()
+        );
+        let query = after.render("volume", &HashMap::new()).unwrap();
+        assert_eq!(
+            query.tags.get(QUERY_HASH_TAG),
+            Some(after.hash("volume").unwrap().as_str())
+        );
+        assert!(matches!(
+            QueryRegistry::default().hash("volume"),
+            Err(RegistryError::UnknownQuery(_))
+        ));
+    }
+}
@@ -0,0 +1,594 @@
+use crate::aggregate::GroupBy;
+use crate::csv;
+use crate::diff::{self, DiffError, ResultDiff};
+use crate::expectations::Expectations;
+use crate::join::{self, JoinKind};
+use crate::pivot;
+use crate::rpc::{ColumnType, GetQueryRunResultsRawResult, GetQueryRunResultsResult};
+use serde::de::DeserializeOwned;
+use serde_json::value::RawValue;
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// A single result row, with values in column order
+pub type Row = Vec<Value>;
+
+/// What `ResultSet::index_by` does when several rows share a key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicateKeyPolicy {
+    KeepFirst,
+    KeepLast,
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum IndexError {
+    #[error("no column named `{0}`")]
+    MissingColumn(String),
+    #[error("several rows have key `{0}`")]
+    DuplicateKey(String),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ResultSet {
+    pub column_names: Vec<String>,
+    pub column_types: Vec<ColumnType>,
+    pub rows: Vec<Row>,
+}
+
+impl ResultSet {
+    /// Whether column `index` holds at least one integer and nothing but integers
+    /// and nulls, e.g. to store a number column as integers instead of floats
+    pub fn is_integral(&self, index: usize) -> bool {
+        let mut values = self
+            .rows
+            .iter()
+            .filter_map(|row| row.get(index))
+            .filter(|value| !value.is_null())
+            .peekable();
+        values.peek().is_some() && values.all(|value| as_integer(value).is_some())
+    }
+
+    pub fn new(column_names: Vec<String>, column_types: Vec<ColumnType>, rows: Vec<Value>) -> Self {
+        let rows = rows
+            .into_iter()
+            .map(|row| normalize_row(&column_names, &column_types, row))
+            .collect();
+
+        Self {
+            column_names,
+            column_types,
+            rows,
+        }
+    }
+
+    /// Builds a result set from a CSV payload without a header row
+    pub fn from_csv(
+        column_names: Vec<String>,
+        column_types: Vec<ColumnType>,
+        payload: &[u8],
+    ) -> Self {
+        let rows = csv::parse_rows(payload, &column_types);
+
+        Self {
+            column_names,
+            column_types,
+            rows,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    /// Position of `column` in `column_names`
+    pub fn column_index(&self, column: &str) -> Option<usize> {
+        self.column_names.iter().position(|name| name == column)
+    }
+
+    /// A view over a subset of the columns, resolved to indices once up front.
+    /// Returns `None` if any of the columns does not exist.
+    pub fn project(&self, columns: &[&str]) -> Option<Projection<'_>> {
+        let indices = columns
+            .iter()
+            .map(|column| self.column_index(column))
+            .collect::<Option<Vec<_>>>()?;
+
+        Some(Projection {
+            result_set: self,
+            indices,
+        })
+    }
+
+    /// Groups rows by the value of `column` for client-side aggregation, e.g.
+    /// `result_set.group_by("token")?.sum("amount")`
+    pub fn group_by(&self, column: &str) -> Option<GroupBy<'_>> {
+        Some(GroupBy::new(self, self.column_index(column)?))
+    }
+
+    /// Joins two result sets on columns with the same names in both. The output has
+    /// every column of `self` followed by the non-key columns of `other`, suffixed
+    /// with `_right` when their name is already taken. As in SQL, rows with a null
+    /// key match nothing, and keys only match values of the same JSON type. Returns
+    /// `None` if a join column is missing from either side.
+    pub fn join(&self, other: &ResultSet, on: &[&str], kind: JoinKind) -> Option<ResultSet> {
+        join::join(self, other, on, kind)
+    }
+
+    /// Rows added, removed and changed in `other`, matched on `key_columns`, which
+    /// must be unique on both sides. Both must have the same columns, in any order.
+    pub fn diff(&self, other: &ResultSet, key_columns: &[&str]) -> Result<ResultDiff, DiffError> {
+        diff::diff(self, other, key_columns)
+    }
+
+    /// Reshapes long rows into a wide table: one row per distinct `index_column`
+    /// value and one column per distinct `key_column` value, filled from `value_column`.
+    /// Column names that would collide, e.g. for `1` and `"1"` or a key named like
+    /// the index column, are suffixed with `_2`, `_3`...
+    pub fn pivot(
+        &self,
+        index_column: &str,
+        key_column: &str,
+        value_column: &str,
+    ) -> Option<ResultSet> {
+        pivot::pivot(self, index_column, key_column, value_column)
+    }
+
+    /// Inverse of `pivot`: every column not in `index_columns` becomes a
+    /// (`key_name`, `value_name`) row
+    pub fn unpivot(
+        &self,
+        index_columns: &[&str],
+        key_name: &str,
+        value_name: &str,
+    ) -> Option<ResultSet> {
+        pivot::unpivot(self, index_columns, key_name, value_name)
+    }
+
+    /// Rows keyed by the value of `column`, for use as a lookup table.
+    /// String values are used as-is, other values by their JSON representation.
+    pub fn index_by(
+        &self,
+        column: &str,
+        on_duplicate: DuplicateKeyPolicy,
+    ) -> Result<HashMap<String, Row>, IndexError> {
+        let index = self
+            .column_index(column)
+            .ok_or_else(|| IndexError::MissingColumn(column.to_string()))?;
+        let mut map = HashMap::with_capacity(self.rows.len());
+
+        for row in &self.rows {
+            let key = value_key(row.get(index).unwrap_or(&Value::Null));
+
+            match (map.contains_key(&key), on_duplicate) {
+                (true, DuplicateKeyPolicy::KeepFirst) => {}
+                (true, DuplicateKeyPolicy::Error) => return Err(IndexError::DuplicateKey(key)),
+                _ => {
+                    map.insert(key, row.clone());
+                }
+            }
+        }
+
+        Ok(map)
+    }
+
+    /// Rows as maps from column name to value
+    pub fn records(&self) -> Vec<HashMap<String, Value>> {
+        self.rows
+            .iter()
+            .map(|row| {
+                self.column_names
+                    .iter()
+                    .cloned()
+                    .zip(row.iter().cloned())
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Deserializes every row into `T`, as an object keyed by column name
+    pub fn rows_as<T: DeserializeOwned>(&self) -> Result<Vec<T>, serde_json::Error> {
+        self.rows
+            .iter()
+            .map(|row| {
+                T::deserialize(Value::Object(
+                    self.column_names
+                        .iter()
+                        .cloned()
+                        .zip(row.iter().cloned())
+                        .collect(),
+                ))
+            })
+            .collect()
+    }
+
+    /// Starts a chain of data-quality checks, e.g.
+    /// `result_set.expect().expect_no_nulls("tx_hash").expect_unique("id").report()`
+    pub fn expect(&self) -> Expectations<'_> {
+        Expectations::new(self)
+    }
+
+    /// Stable hash over the column names and rows, in row order
+    pub fn fingerprint(&self) -> u64 {
+        let mut fingerprinter = Fingerprinter::new(&self.column_names, true);
+        fingerprinter.update(&self.rows);
+        fingerprinter.finish()
+    }
+
+    /// Stable hash over the column names and rows, ignoring row order
+    pub fn fingerprint_unordered(&self) -> u64 {
+        let mut fingerprinter = Fingerprinter::new(&self.column_names, false);
+        fingerprinter.update(&self.rows);
+        fingerprinter.finish()
+    }
+
+    /// `row_hash` of every row, in row order
+    pub fn row_hashes(&self) -> Vec<u64> {
+        self.rows.iter().map(row_hash).collect()
+    }
+}
+
+/// Stable hash of a row's values, the same across builds and platforms
+pub fn row_hash(row: &Row) -> u64 {
+    let mut hasher = Fnv64::default();
+    hasher.write_value(row);
+    hasher.finish()
+}
+
+/// Computes `ResultSet::fingerprint`, or `fingerprint_unordered`, a batch of rows
+/// at a time, e.g. while streaming pages
+#[derive(Debug, Clone)]
+pub struct Fingerprinter {
+    hasher: Fnv64,
+    /// Row hashes to sort before hashing, when ignoring row order
+    row_hashes: Option<Vec<u64>>,
+}
+
+impl Fingerprinter {
+    pub fn new(column_names: &[String], ordered: bool) -> Self {
+        let mut hasher = Fnv64::default();
+        hasher.write_value(column_names);
+        Self {
+            hasher,
+            row_hashes: (!ordered).then(Vec::new),
+        }
+    }
+
+    pub fn update(&mut self, rows: &[Row]) {
+        match &mut self.row_hashes {
+            Some(row_hashes) => row_hashes.extend(rows.iter().map(row_hash)),
+            None => {
+                for row in rows {
+                    self.hasher.write_value(row);
+                }
+            }
+        }
+    }
+
+    pub fn finish(mut self) -> u64 {
+        if let Some(mut row_hashes) = self.row_hashes.take() {
+            row_hashes.sort_unstable();
+            for hash in row_hashes {
+                self.hasher.write(&hash.to_le_bytes());
+            }
+        }
+        self.hasher.finish()
+    }
+}
+
+impl From<GetQueryRunResultsResult> for ResultSet {
+    fn from(res: GetQueryRunResultsResult) -> Self {
+        Self::new(res.column_names, res.column_types, res.rows)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Projection<'a> {
+    result_set: &'a ResultSet,
+    indices: Vec<usize>,
+}
+
+impl<'a> Projection<'a> {
+    pub fn column_names(&self) -> Vec<&'a str> {
+        self.indices
+            .iter()
+            .map(|&i| self.result_set.column_names[i].as_str())
+            .collect()
+    }
+
+    pub fn column_types(&self) -> Vec<&'a ColumnType> {
+        self.indices
+            .iter()
+            .filter_map(|&i| self.result_set.column_types.get(i))
+            .collect()
+    }
+
+    /// Indices of the projected columns in the underlying result set
+    pub fn indices(&self) -> &[usize] {
+        &self.indices
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = Vec<&'a Value>> + '_ {
+        self.result_set.rows.iter().map(|row| {
+            self.indices
+                .iter()
+                .map(|&i| row.get(i).unwrap_or(&Value::Null))
+                .collect()
+        })
+    }
+
+    /// Copies the projected columns into a standalone result set
+    pub fn to_result_set(&self) -> ResultSet {
+        ResultSet {
+            column_names: self.column_names().into_iter().map(String::from).collect(),
+            column_types: self.column_types().into_iter().cloned().collect(),
+            rows: self
+                .rows()
+                .map(|row| row.into_iter().cloned().collect())
+                .collect(),
+        }
+    }
+}
+
+/// A row kept as raw JSON, only parsed when a column is accessed
+#[derive(Debug, Clone)]
+pub struct LazyRow(Box<RawValue>);
+
+impl LazyRow {
+    pub fn raw(&self) -> &str {
+        self.0.get()
+    }
+
+    /// Parses the value at `index` for array and CSV rows, or under `column` for
+    /// object rows. CSV fields are converted according to `column_types`.
+    pub fn get_as<T: DeserializeOwned>(
+        &self,
+        index: usize,
+        column: &str,
+        column_types: &[ColumnType],
+    ) -> Result<Option<T>, serde_json::Error> {
+        let raw = self.0.get().trim_start();
+        if raw.starts_with('"') {
+            let record = serde_json::from_str::<String>(raw)?;
+            return csv::parse_record(&record, column_types)
+                .into_iter()
+                .nth(index)
+                .map(serde_json::from_value)
+                .transpose();
+        }
+
+        let value = if raw.starts_with('{') {
+            serde_json::from_str::<HashMap<&str, &RawValue>>(raw)?.remove(column)
+        } else {
+            serde_json::from_str::<Vec<&RawValue>>(raw)?
+                .get(index)
+                .copied()
+        };
+
+        value
+            .map(|value| serde_json::from_str(value.get()))
+            .transpose()
+    }
+
+    /// Fully parses the row
+    pub fn parse(
+        &self,
+        column_names: &[String],
+        column_types: &[ColumnType],
+    ) -> Result<Row, serde_json::Error> {
+        Ok(normalize_row(
+            column_names,
+            column_types,
+            serde_json::from_str(self.0.get())?,
+        ))
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LazyResultSet {
+    pub column_names: Vec<String>,
+    pub column_types: Vec<ColumnType>,
+    pub rows: Vec<LazyRow>,
+}
+
+impl LazyResultSet {
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    pub fn column_index(&self, column: &str) -> Option<usize> {
+        self.column_names.iter().position(|name| name == column)
+    }
+
+    /// Parses a single cell, leaving the rest of the row untouched
+    pub fn get_as<T: DeserializeOwned>(
+        &self,
+        row: usize,
+        column: &str,
+    ) -> Result<Option<T>, serde_json::Error> {
+        match (self.rows.get(row), self.column_index(column)) {
+            (Some(row), Some(index)) => row.get_as(index, column, &self.column_types),
+            _ => Ok(None),
+        }
+    }
+
+    /// Parses every row into a regular result set
+    pub fn materialize(&self) -> Result<ResultSet, serde_json::Error> {
+        Ok(ResultSet {
+            column_names: self.column_names.clone(),
+            column_types: self.column_types.clone(),
+            rows: self
+                .rows
+                .iter()
+                .map(|row| row.parse(&self.column_names, &self.column_types))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+impl From<GetQueryRunResultsRawResult> for LazyResultSet {
+    fn from(res: GetQueryRunResultsRawResult) -> Self {
+        Self {
+            column_names: res.column_names,
+            column_types: res.column_types,
+            rows: res.rows.into_iter().map(LazyRow).collect(),
+        }
+    }
+}
+
+/// String form of a value used as a key: strings as-is, anything else as JSON
+pub(crate) fn value_key(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        value => value.to_string(),
+    }
+}
+
+/// Value of a number column as an integer, `None` if it's null or has a fraction
+pub(crate) fn as_integer(value: &Value) -> Option<i64> {
+    match value {
+        Value::Number(n) => n.as_i64(),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+/// Rows may come back as arrays, as objects keyed by column name, or as CSV
+/// records when results are requested in CSV format
+pub(crate) fn normalize_row(
+    column_names: &[String],
+    column_types: &[ColumnType],
+    row: Value,
+) -> Row {
+    match row {
+        Value::Array(values) => values,
+        Value::String(record) => csv::parse_record(&record, column_types),
+        Value::Object(mut map) => column_names
+            .iter()
+            .map(|name| map.remove(name).unwrap_or(Value::Null))
+            .collect(),
+        value => vec![value],
+    }
+}
+
+/// FNV-1a, used instead of `DefaultHasher` so fingerprints are stable across builds
+#[derive(Debug, Clone)]
+pub(crate) struct Fnv64(u64);
+
+impl Default for Fnv64 {
+    fn default() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+}
+
+impl Fnv64 {
+    pub(crate) fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    /// Hashes the canonical JSON encoding of `value`, followed by a separator
+    pub(crate) fn write_value<T: serde::Serialize + ?Sized>(&mut self, value: &T) {
+        self.write(&serde_json::to_vec(value).unwrap_or_default());
+        self.write(b"\n");
+    }
+
+    pub(crate) fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn columns() -> (Vec<String>, Vec<ColumnType>) {
+        (
+            vec!["id".to_string(), "name".to_string()],
+            vec![ColumnType::Number, ColumnType::String],
+        )
+    }
+
+    #[test]
+    fn normalizes_array_object_and_csv_rows() {
+        let (column_names, column_types) = columns();
+        for row in [
+            json!([1, "a,b"]),
+            json!({"name": "a,b", "id": 1}),
+            json!("1,\"a,b\""),
+        ] {
+            assert_eq!(
+                normalize_row(&column_names, &column_types, row),
+                [json!(1), json!("a,b")]
+            );
+        }
+    }
+
+    #[test]
+    fn fingerprints_are_stable_and_optionally_order_insensitive() {
+        let (column_names, column_types) = columns();
+        let a = ResultSet::new(
+            column_names.clone(),
+            column_types.clone(),
+            vec![json!([1, "a"]), json!([2, "b"])],
+        );
+        let b = ResultSet::new(
+            column_names,
+            column_types,
+            vec![json!([2, "b"]), json!([1, "a"])],
+        );
+
+        // FNV-1a of `[1,"a"]\n`, must not change across releases
+        assert_eq!(row_hash(&vec![json!(1), json!("a")]), 0xff98_8a79_550f_ed1f);
+        assert_eq!(a.row_hashes(), [row_hash(&a.rows[0]), row_hash(&a.rows[1])]);
+        assert_ne!(a.fingerprint(), b.fingerprint());
+        assert_eq!(a.fingerprint_unordered(), b.fingerprint_unordered());
+        assert_ne!(a.fingerprint(), a.fingerprint_unordered());
+    }
+
+    #[test]
+    fn fingerprinter_matches_across_batches() {
+        let (column_names, column_types) = columns();
+        let rows = vec![json!([1, "a"]), json!([2, "b"]), json!([3, "c"])];
+        let result_set = ResultSet::new(column_names.clone(), column_types, rows);
+
+        for ordered in [true, false] {
+            let mut fingerprinter = Fingerprinter::new(&column_names, ordered);
+            fingerprinter.update(&result_set.rows[..1]);
+            fingerprinter.update(&result_set.rows[1..]);
+            let expected = if ordered {
+                result_set.fingerprint()
+            } else {
+                result_set.fingerprint_unordered()
+            };
+            assert_eq!(fingerprinter.finish(), expected);
+        }
+    }
+
+    #[test]
+    fn lazy_rows_decode_csv_records() {
+        let (column_names, column_types) = columns();
+        let row = LazyRow(RawValue::from_string(r#""1,\"a,b\"""#.to_string()).unwrap());
+
+        assert_eq!(
+            row.parse(&column_names, &column_types).unwrap(),
+            [json!(1), json!("a,b")]
+        );
+        assert_eq!(
+            row.get_as::<String>(1, "name", &column_types).unwrap(),
+            Some("a,b".to_string())
+        );
+        assert_eq!(row.get_as::<i64>(0, "id", &column_types).unwrap(), Some(1));
+    }
+}
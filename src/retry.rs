@@ -0,0 +1,246 @@
+use crate::error::rate_limit;
+use crate::error::ApiError;
+use crate::flipside::{ClientError, ErrorCategory, Flipside, Query, QueryRunError};
+use crate::rpc::QueryRun;
+use jsonrpsee::http_client::transport::Error as TransportError;
+use std::future::Future;
+use std::time::Duration;
+
+/// Retries of RPC calls that failed for transient reasons (network errors, request
+/// timeouts and 5xx responses), with exponential backoff. Rate limited calls are
+/// retried after the delay the API asks for, under a separate budget.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Total attempts per call, 1 disables retries
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    /// Retries of a rate limited call before `ApiError::RateLimited` is returned
+    pub rate_limit_retries: u32,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(10),
+            rate_limit_retries: 3,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            rate_limit_retries: 0,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) async fn retry<T, F, Fut>(&self, request: F) -> Result<T, ClientError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, ClientError>>,
+    {
+        self.retry_if(request, true).await
+    }
+
+    /// Like `retry`, but only retries rate limited calls, which the API rejected
+    /// without acting on them. For calls that aren't idempotent, where a network
+    /// failure doesn't tell whether the server acted on the request.
+    pub(crate) async fn retry_rate_limited<T, F, Fut>(&self, request: F) -> Result<T, ClientError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, ClientError>>,
+    {
+        self.retry_if(request, false).await
+    }
+
+    async fn retry_if<T, F, Fut>(&self, mut request: F, transient: bool) -> Result<T, ClientError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, ClientError>>,
+    {
+        let mut delay = self.base_delay;
+        let mut attempts = 1;
+        let mut rate_limit_retries = 0;
+
+        loop {
+            let err = match request().await {
+                Ok(value) => return Ok(value),
+                Err(err) => err,
+            };
+
+            if let Some(retry_after) = rate_limit(&err) {
+                if rate_limit_retries >= self.rate_limit_retries {
+                    return Err(err);
+                }
+                rate_limit_retries += 1;
+                match retry_after {
+                    Some(retry_after) => tokio::time::sleep(retry_after).await,
+                    None => {
+                        tokio::time::sleep(delay).await;
+                        delay = (delay * 2).min(self.max_delay);
+                    }
+                }
+            } else if transient && attempts < self.max_attempts && is_transient(&err) {
+                attempts += 1;
+                tokio::time::sleep(delay).await;
+                delay = (delay * 2).min(self.max_delay);
+            } else {
+                return Err(err);
+            }
+        }
+    }
+}
+
+/// Whether retrying the call that failed with `err` may succeed
+pub fn is_transient(err: &ClientError) -> bool {
+    match err {
+        ClientError::RequestTimeout => true,
+        ClientError::Transport(err) => match err.downcast_ref::<TransportError>() {
+            Some(TransportError::Rejected { status_code }) => *status_code >= 500,
+            Some(TransportError::Http(_)) => true,
+            Some(_) => false,
+            None => true,
+        },
+        _ => false,
+    }
+}
+
+/// Re-runs of queries that failed as a whole for a reason that may not happen
+/// again, e.g. a shard of a partitioned query hitting a transient Snowflake error
+#[derive(Debug, Clone, Copy)]
+pub struct RunRetryPolicy {
+    /// Total runs per query, 1 disables retries
+    pub max_attempts: u32,
+    /// Wait before each re-run
+    pub delay: Duration,
+}
+
+impl Default for RunRetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            delay: Duration::from_secs(5),
+        }
+    }
+}
+
+impl RunRetryPolicy {
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    /// Runs `query`, trying again after failures `is_retryable` accepts. A run
+    /// that timed out client-side may still be going, so it is waited on again
+    /// rather than resubmitted, unless it was cancelled on timeout.
+    pub async fn run(&self, flipside: &Flipside, query: Query) -> Result<QueryRun, QueryRunError> {
+        self.run_counted(flipside, query).await.0
+    }
+
+    /// Like `run`, also returning how many attempts it took
+    pub async fn run_counted(
+        &self,
+        flipside: &Flipside,
+        query: Query,
+    ) -> (Result<QueryRun, QueryRunError>, u32) {
+        let mut attempts = 1;
+        let mut res = flipside.run(query.clone()).await;
+        loop {
+            match res {
+                Err(err) if attempts < self.max_attempts && is_retryable(&err) => {
+                    attempts += 1;
+                    tokio::time::sleep(self.delay).await;
+                    res = match err {
+                        QueryRunError::Timeout { query_run, .. }
+                            if !query_run.state.is_terminal() =>
+                        {
+                            flipside.wait_for_completion(query_run.id).await
+                        }
+                        _ => flipside.run(query.clone()).await,
+                    };
+                }
+                res => return (res, attempts),
+            }
+        }
+    }
+}
+
+/// Whether trying the query again after it failed with `err` may succeed: API
+/// failures other than rejected requests and network errors, timeouts, and
+/// execution errors other than syntax and permission errors. After a network error
+/// the run may exist on the server, so running the query again could duplicate it.
+pub fn is_retryable(err: &QueryRunError) -> bool {
+    match err {
+        QueryRunError::RpcError(err) => !matches!(
+            err,
+            ApiError::UserError { .. } | ApiError::NotFound { .. } | ApiError::Transport(_)
+        ),
+        QueryRunError::Timeout { .. } => true,
+        QueryRunError::ExecutionError(err) => !matches!(
+            err.category(),
+            ErrorCategory::Syntax | ErrorCategory::Permission
+        ),
+        QueryRunError::SchemaMismatch(_)
+        | QueryRunError::Cancelled(_)
+        | QueryRunError::Aborted { .. } => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flipside::ExecutionError;
+    use jsonrpsee::types::ErrorObjectOwned;
+
+    #[test]
+    fn only_timeouts_and_server_failures_are_transient() {
+        let rejected =
+            |status_code| ClientError::Transport(TransportError::Rejected { status_code }.into());
+        assert!(is_transient(&ClientError::RequestTimeout));
+        assert!(is_transient(&rejected(503)));
+        assert!(!is_transient(&rejected(400)));
+        assert!(!is_transient(&ClientError::Call(ErrorObjectOwned::owned(
+            -32602,
+            "invalid params",
+            None::<()>,
+        ))));
+    }
+
+    #[test]
+    fn rejected_requests_and_sql_errors_are_not_retryable() {
+        let api_error = |err| QueryRunError::RpcError(err);
+        assert!(is_retryable(&api_error(ApiError::ServerError {
+            code: 500,
+            message: String::new(),
+        })));
+        assert!(!is_retryable(&api_error(ApiError::UserError {
+            code: 400,
+            message: String::new(),
+        })));
+        assert!(!is_retryable(&api_error(ApiError::NotFound {
+            message: String::new(),
+        })));
+        assert!(!is_retryable(&api_error(ApiError::Transport(
+            ClientError::RequestTimeout
+        ))));
+
+        let execution_error = |message: &str| {
+            QueryRunError::ExecutionError(ExecutionError {
+                name: None,
+                message: Some(message.to_string()),
+                data: None,
+            })
+        };
+        assert!(!is_retryable(&execution_error(
+            "SQL compilation error: syntax error line 1 at position 0"
+        )));
+        assert!(is_retryable(&execution_error("Internal error")));
+    }
+}
@@ -0,0 +1,84 @@
+use crate::result_set::ResultSet;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+#[cfg(feature = "derive")]
+pub use flipside_derive::FromQueryRow;
+
+#[derive(Debug, thiserror::Error)]
+pub enum RowError {
+    #[error("no column named `{0}`")]
+    MissingColumn(String),
+    #[error("invalid value in column `{column}`")]
+    InvalidValue {
+        column: String,
+        #[source]
+        error: serde_json::Error,
+    },
+}
+
+/// Builds a value from a result row, usually through `#[derive(FromQueryRow)]`
+/// (behind the `derive` feature)
+pub trait FromQueryRow: Sized {
+    fn from_row(column_names: &[String], row: &[Value]) -> Result<Self, RowError>;
+}
+
+/// Reads `column` from `row` (matching its name case-insensitively if there's no
+/// exact match) and converts it to `T`. Strings holding a number, boolean or JSON
+/// document are also accepted for non-string types.
+pub fn get<T: DeserializeOwned>(
+    column_names: &[String],
+    row: &[Value],
+    column: &str,
+) -> Result<T, RowError> {
+    let value = lookup(column_names, row, column)
+        .ok_or_else(|| RowError::MissingColumn(column.to_string()))?;
+    convert(value).map_err(|error| RowError::InvalidValue {
+        column: column.to_string(),
+        error,
+    })
+}
+
+/// Like `get`, but missing and null columns become `T::default()`
+pub fn get_or_default<T: DeserializeOwned + Default>(
+    column_names: &[String],
+    row: &[Value],
+    column: &str,
+) -> Result<T, RowError> {
+    match lookup(column_names, row, column) {
+        None | Some(Value::Null) => Ok(T::default()),
+        Some(_) => get(column_names, row, column),
+    }
+}
+
+fn lookup<'a>(column_names: &[String], row: &'a [Value], column: &str) -> Option<&'a Value> {
+    column_names
+        .iter()
+        .position(|name| name == column)
+        .or_else(|| {
+            column_names
+                .iter()
+                .position(|name| name.eq_ignore_ascii_case(column))
+        })
+        .and_then(|index| row.get(index))
+}
+
+fn convert<T: DeserializeOwned>(value: &Value) -> Result<T, serde_json::Error> {
+    match T::deserialize(value) {
+        Err(err) => match value {
+            Value::String(s) => serde_json::from_str(s).map_err(|_| err),
+            _ => Err(err),
+        },
+        ok => ok,
+    }
+}
+
+impl ResultSet {
+    /// Converts every row with `T`'s `FromQueryRow` implementation
+    pub fn rows_into<T: FromQueryRow>(&self) -> Result<Vec<T>, RowError> {
+        self.rows
+            .iter()
+            .map(|row| T::from_row(&self.column_names, row))
+            .collect()
+    }
+}
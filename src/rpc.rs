@@ -1,10 +1,16 @@
 use std::collections::HashMap;
+use std::time::SystemTime;
 
+use crate::tags::Tags;
+#[cfg(feature = "server")]
+use jsonrpsee::core::RpcResult;
 use jsonrpsee::proc_macros::rpc;
 use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
 use serde_json::Value;
 
-#[derive(Clone, Serialize, Deserialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "openrpc", derive(schemars::JsonSchema))]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum QueryState {
     QueryStateReady,
@@ -15,13 +21,26 @@ pub enum QueryState {
     QueryStateCancelled,
 }
 
+impl QueryState {
+    /// Whether the run can no longer change state
+    pub fn is_terminal(&self) -> bool {
+        matches!(
+            self,
+            QueryState::QueryStateSuccess
+                | QueryState::QueryStateFailed
+                | QueryState::QueryStateCancelled
+        )
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openrpc", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct QueryRequest {
     pub id: String,
     pub sql_statement_id: String,
     pub user_id: String,
-    pub tags: HashMap<String, Option<String>>,
+    pub tags: Tags,
     pub max_age_minutes: u64,
     #[serde(rename = "resultTTLHours")]
     pub result_ttl_hours: u64,
@@ -33,15 +52,16 @@ pub struct QueryRequest {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openrpc", derive(schemars::JsonSchema))]
 #[serde(untagged)]
 pub enum FileNames {
     Single(String),
     Multiple(Vec<String>),
 }
 
-impl Into<Vec<String>> for FileNames {
-    fn into(self) -> Vec<String> {
-        match self {
+impl From<FileNames> for Vec<String> {
+    fn from(file_names: FileNames) -> Self {
+        match file_names {
             FileNames::Single(s) => vec![s],
             FileNames::Multiple(v) => v,
         }
@@ -49,6 +69,7 @@ impl Into<Vec<String>> for FileNames {
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openrpc", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct QueryRun {
     pub id: String,
@@ -70,7 +91,7 @@ pub struct QueryRun {
     pub ended_at: Option<String>,
     pub row_count: Option<usize>,
     pub total_size: Option<String>,
-    pub tags: HashMap<String, Option<String>>,
+    pub tags: Tags,
     pub data_source_id: String,
     pub user_id: String,
     pub created_at: String,
@@ -82,14 +103,16 @@ pub struct QueryRun {
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openrpc", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct ColumnMetadata {
-    types: Vec<String>,
-    columns: Vec<String>,
-    col_type_map: HashMap<String, String>,
+    pub types: Vec<String>,
+    pub columns: Vec<String>,
+    pub col_type_map: HashMap<String, String>,
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openrpc", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct SqlStatement {
     pub id: String,
@@ -97,12 +120,13 @@ pub struct SqlStatement {
     pub sql: String,
     pub column_metadata: Option<ColumnMetadata>,
     pub user_id: String,
-    pub tags: HashMap<String, Option<String>>,
+    pub tags: Tags,
     pub created_at: String,
     pub updated_at: String,
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openrpc", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct CreateQueryRunResult {
     pub query_request: QueryRequest,
@@ -111,13 +135,39 @@ pub struct CreateQueryRunResult {
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openrpc", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct SortBy {
     pub column: String,
-    pub direction: String,
+    pub direction: SortDirection,
 }
 
-#[derive(Clone, Serialize, Deserialize, Debug)]
+impl SortBy {
+    pub fn asc(column: impl Into<String>) -> Self {
+        Self {
+            column: column.into(),
+            direction: SortDirection::Asc,
+        }
+    }
+
+    pub fn desc(column: impl Into<String>) -> Self {
+        Self {
+            column: column.into(),
+            direction: SortDirection::Desc,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "openrpc", derive(schemars::JsonSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum SortDirection {
+    Asc,
+    Desc,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "openrpc", derive(schemars::JsonSchema))]
 #[serde(rename_all = "lowercase")]
 pub enum FilterKey {
     Column,
@@ -132,14 +182,224 @@ pub enum FilterKey {
     NotIn,
 }
 
+/// Value of a filter condition. `In` and `NotIn` take a list.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "openrpc", derive(schemars::JsonSchema))]
+#[serde(untagged)]
+pub enum FilterValue {
+    Boolean(bool),
+    Number(serde_json::Number),
+    String(String),
+    List(Vec<FilterValue>),
+    /// For date columns, sent as an RFC 3339 UTC timestamp like the dates in
+    /// results, e.g. `2024-01-01T00:00:00.000Z`
+    #[serde(with = "timestamp")]
+    #[cfg_attr(feature = "openrpc", schemars(with = "String"))]
+    Timestamp(SystemTime),
+}
+
+mod timestamp {
+    use crate::stats::{format_timestamp, parse_timestamp};
+    use serde::{de, Deserialize, Deserializer, Serializer};
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    pub fn serialize<S: Serializer>(time: &SystemTime, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format_timestamp(*time))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<SystemTime, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        parse_timestamp(&s)
+            .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+            .map(|since_epoch| UNIX_EPOCH + since_epoch)
+            .ok_or_else(|| de::Error::custom(format!("invalid timestamp `{}`", s)))
+    }
+}
+
+impl From<&str> for FilterValue {
+    fn from(value: &str) -> Self {
+        FilterValue::String(value.to_string())
+    }
+}
+
+impl From<String> for FilterValue {
+    fn from(value: String) -> Self {
+        FilterValue::String(value)
+    }
+}
+
+impl From<bool> for FilterValue {
+    fn from(value: bool) -> Self {
+        FilterValue::Boolean(value)
+    }
+}
+
+impl From<i32> for FilterValue {
+    fn from(value: i32) -> Self {
+        FilterValue::Number(value.into())
+    }
+}
+
+impl From<u32> for FilterValue {
+    fn from(value: u32) -> Self {
+        FilterValue::Number(value.into())
+    }
+}
+
+impl From<i64> for FilterValue {
+    fn from(value: i64) -> Self {
+        FilterValue::Number(value.into())
+    }
+}
+
+impl From<u64> for FilterValue {
+    fn from(value: u64) -> Self {
+        FilterValue::Number(value.into())
+    }
+}
+
+/// Non-finite numbers become strings, which the API rejects
+impl From<f64> for FilterValue {
+    fn from(value: f64) -> Self {
+        serde_json::Number::from_f64(value)
+            .map(FilterValue::Number)
+            .unwrap_or_else(|| FilterValue::String(value.to_string()))
+    }
+}
+
+impl From<SystemTime> for FilterValue {
+    fn from(value: SystemTime) -> Self {
+        FilterValue::Timestamp(value)
+    }
+}
+
+impl<T: Into<FilterValue>> From<Vec<T>> for FilterValue {
+    fn from(values: Vec<T>) -> Self {
+        FilterValue::List(values.into_iter().map(Into::into).collect())
+    }
+}
+
+/// A condition on a single column, sent as `{"column": <column>, <op>: <value>}`
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(
+    into = "HashMap<FilterKey, FilterValue>",
+    try_from = "HashMap<FilterKey, FilterValue>"
+)]
+pub struct Filter {
+    pub column: String,
+    pub op: FilterKey,
+    pub value: FilterValue,
+}
+
+impl Filter {
+    pub fn new(column: impl Into<String>, op: FilterKey, value: impl Into<FilterValue>) -> Self {
+        Self {
+            column: column.into(),
+            op,
+            value: value.into(),
+        }
+    }
+
+    pub fn eq(column: impl Into<String>, value: impl Into<FilterValue>) -> Self {
+        Self::new(column, FilterKey::Eq, value)
+    }
+
+    pub fn neq(column: impl Into<String>, value: impl Into<FilterValue>) -> Self {
+        Self::new(column, FilterKey::Neq, value)
+    }
+
+    pub fn gt(column: impl Into<String>, value: impl Into<FilterValue>) -> Self {
+        Self::new(column, FilterKey::Gt, value)
+    }
+
+    pub fn gte(column: impl Into<String>, value: impl Into<FilterValue>) -> Self {
+        Self::new(column, FilterKey::Gte, value)
+    }
+
+    pub fn lt(column: impl Into<String>, value: impl Into<FilterValue>) -> Self {
+        Self::new(column, FilterKey::Lt, value)
+    }
+
+    pub fn lte(column: impl Into<String>, value: impl Into<FilterValue>) -> Self {
+        Self::new(column, FilterKey::Lte, value)
+    }
+
+    /// SQL `LIKE` pattern, e.g. `"0x%"`
+    pub fn like(column: impl Into<String>, pattern: impl Into<String>) -> Self {
+        Self::new(column, FilterKey::Like, pattern.into())
+    }
+
+    pub fn in_list<T: Into<FilterValue>>(
+        column: impl Into<String>,
+        values: impl IntoIterator<Item = T>,
+    ) -> Self {
+        Self::new(
+            column,
+            FilterKey::In,
+            FilterValue::List(values.into_iter().map(Into::into).collect()),
+        )
+    }
+
+    pub fn not_in<T: Into<FilterValue>>(
+        column: impl Into<String>,
+        values: impl IntoIterator<Item = T>,
+    ) -> Self {
+        Self::new(
+            column,
+            FilterKey::NotIn,
+            FilterValue::List(values.into_iter().map(Into::into).collect()),
+        )
+    }
+}
+
+impl From<Filter> for HashMap<FilterKey, FilterValue> {
+    fn from(filter: Filter) -> Self {
+        HashMap::from([
+            (FilterKey::Column, FilterValue::String(filter.column)),
+            (filter.op, filter.value),
+        ])
+    }
+}
+
+impl TryFrom<HashMap<FilterKey, FilterValue>> for Filter {
+    type Error = String;
+
+    fn try_from(mut map: HashMap<FilterKey, FilterValue>) -> Result<Self, Self::Error> {
+        let Some(FilterValue::String(column)) = map.remove(&FilterKey::Column) else {
+            return Err("filter is missing its column".to_string());
+        };
+        let mut conditions = map.into_iter();
+        match (conditions.next(), conditions.next()) {
+            (Some((op, value)), None) => Ok(Self { column, op, value }),
+            _ => Err(format!(
+                "filter on {} must have exactly one condition",
+                column
+            )),
+        }
+    }
+}
+
+#[cfg(feature = "openrpc")]
+impl schemars::JsonSchema for Filter {
+    fn schema_name() -> String {
+        "Filter".to_string()
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        <HashMap<FilterKey, FilterValue>>::json_schema(gen)
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openrpc", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct Pagination {
     pub number: usize,
     pub size: usize,
 }
 
-#[derive(Clone, Serialize, Deserialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "openrpc", derive(schemars::JsonSchema))]
 #[serde(rename_all = "lowercase")]
 pub enum ColumnType {
     String,
@@ -152,6 +412,7 @@ pub enum ColumnType {
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openrpc", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct PaginationDetails {
     pub current_page_number: usize,
@@ -161,6 +422,7 @@ pub struct PaginationDetails {
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openrpc", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct GetQueryRunResultsResult {
     pub column_names: Vec<String>,
@@ -172,6 +434,20 @@ pub struct GetQueryRunResultsResult {
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openrpc", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct GetQueryRunResultsRawResult {
+    pub column_names: Vec<String>,
+    pub column_types: Vec<ColumnType>,
+    #[cfg_attr(feature = "openrpc", schemars(with = "Vec<Value>"))]
+    pub rows: Vec<Box<RawValue>>,
+    pub page: PaginationDetails,
+    pub original_query_run: QueryRun,
+    pub redirected_to_query_run: Option<QueryRun>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openrpc", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct GetQueryRunResult {
     pub query_run: QueryRun,
@@ -179,47 +455,70 @@ pub struct GetQueryRunResult {
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openrpc", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct GetSqlStatementResult {
+    pub sql_statement: SqlStatement,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openrpc", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct CancelQueryRunResult {
     pub canceled_query_run: QueryRun,
 }
 
-#[derive(Clone, Serialize, Deserialize, Debug)]
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "openrpc", derive(schemars::JsonSchema))]
 #[serde(rename_all = "lowercase")]
 pub enum QueryFormat {
     Json,
     Csv,
 }
 
-#[derive(Clone, Serialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openrpc", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct GetQueryRunResultsParams {
     pub query_run_id: String,
     pub format: QueryFormat,
     pub sort_by: Vec<SortBy>,
-    pub filters: Vec<HashMap<FilterKey, String>>,
+    pub filters: Vec<Filter>,
     pub page: Option<Pagination>,
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openrpc", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct CreateQueryRunParams {
     #[serde(rename = "resultTTLHours")]
     pub result_ttl_hours: u64,
     pub max_age_minutes: u64,
     pub sql: String,
-    pub tags: HashMap<String, Option<String>>,
+    pub tags: Tags,
     pub data_source: String,
     pub data_provider: String,
+    /// Overrides the data source's default statement timeout
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub statement_timeout_seconds: Option<u64>,
 }
 
-#[derive(Clone, Serialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openrpc", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct QueryRunIdParams {
     pub query_run_id: String,
 }
 
-#[rpc(client)]
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openrpc", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct SqlStatementIdParams {
+    pub sql_statement_id: String,
+}
+
+#[cfg_attr(not(feature = "server"), rpc(client))]
+#[cfg_attr(feature = "server", rpc(client, server))]
 pub trait Rpc {
     #[method(name = "getQueryRunResults")]
     async fn get_query_run_results(
@@ -236,6 +535,65 @@ pub trait Rpc {
     #[method(name = "getQueryRun")]
     async fn get_query_run(&self, params: QueryRunIdParams) -> RpcResult<GetQueryRunResult>;
 
+    #[method(name = "getSqlStatement")]
+    async fn get_sql_statement(
+        &self,
+        params: SqlStatementIdParams,
+    ) -> RpcResult<GetSqlStatementResult>;
+
     #[method(name = "cancelQueryRun")]
     async fn cancel_query_run(&self, params: QueryRunIdParams) -> RpcResult<CancelQueryRunResult>;
 }
+
+/// Client-only variants of `Rpc` methods, kept out of the server trait since they
+/// share a method name with their `Rpc` counterpart
+#[rpc(client)]
+pub trait RawRpc {
+    #[method(name = "getQueryRunResults")]
+    async fn get_query_run_results_raw(
+        &self,
+        params: GetQueryRunResultsParams,
+    ) -> RpcResult<GetQueryRunResultsRawResult>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn filters_serialize_to_the_wire_format() {
+        let filters = [
+            Filter::gt("amount", 100),
+            Filter::eq("symbol", "ETH"),
+            Filter::in_list("id", [1u64, 2]),
+            Filter::gte(
+                "block_timestamp",
+                UNIX_EPOCH + Duration::from_millis(1_704_067_200_500),
+            ),
+        ];
+
+        assert_eq!(
+            serde_json::to_value(&filters).unwrap(),
+            json!([
+                {"column": "amount", "gt": 100},
+                {"column": "symbol", "eq": "ETH"},
+                {"column": "id", "in": [1, 2]},
+                {"column": "block_timestamp", "gte": "2024-01-01T00:00:00.500Z"},
+            ])
+        );
+    }
+
+    #[test]
+    fn filters_round_trip() {
+        let filter = Filter::like("tx_hash", "0x%");
+        let json = serde_json::to_string(&filter).unwrap();
+        assert_eq!(serde_json::from_str::<Filter>(&json).unwrap(), filter);
+
+        assert!(serde_json::from_value::<Filter>(json!({"eq": 1})).is_err());
+        assert!(
+            serde_json::from_value::<Filter>(json!({"column": "a", "eq": 1, "gt": 2})).is_err()
+        );
+    }
+}
@@ -0,0 +1,221 @@
+use crate::error::ApiError;
+use crate::flipside::Flipside;
+use crate::rpc::{ColumnMetadata, ColumnType, QueryRun};
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Column names and types of a result set
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Schema {
+    pub columns: Vec<(String, ColumnType)>,
+}
+
+/// Differences between two schemas, from the point of view of the first one
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SchemaDiff {
+    /// Columns only present in the second schema
+    pub added: Vec<(String, ColumnType)>,
+    /// Columns only present in the first schema
+    pub removed: Vec<(String, ColumnType)>,
+    /// Columns present in both with different types, as (name, before, after)
+    pub retyped: Vec<(String, ColumnType, ColumnType)>,
+}
+
+impl SchemaDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.retyped.is_empty()
+    }
+}
+
+impl Schema {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn column(mut self, name: impl Into<String>, column_type: ColumnType) -> Self {
+        self.columns.push((name.into(), column_type));
+        self
+    }
+
+    pub fn from_metadata(metadata: &ColumnMetadata) -> Self {
+        Self {
+            columns: metadata
+                .columns
+                .iter()
+                .zip(&metadata.types)
+                .map(|(name, column_type)| {
+                    (
+                        name.clone(),
+                        serde_json::from_value(Value::String(column_type.to_lowercase()))
+                            .unwrap_or(ColumnType::Unknown),
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    fn get(&self, name: &str) -> Option<&ColumnType> {
+        self.columns
+            .iter()
+            .find(|(column, _)| column.eq_ignore_ascii_case(name))
+            .map(|(_, column_type)| column_type)
+    }
+
+    /// Compares column names case-insensitively
+    pub fn diff(&self, other: &Schema) -> SchemaDiff {
+        let mut diff = SchemaDiff::default();
+
+        for (name, column_type) in &self.columns {
+            match other.get(name) {
+                None => diff.removed.push((name.clone(), column_type.clone())),
+                Some(other_type) if other_type != column_type => {
+                    diff.retyped
+                        .push((name.clone(), column_type.clone(), other_type.clone()))
+                }
+                _ => {}
+            }
+        }
+
+        for (name, column_type) in &other.columns {
+            if self.get(name).is_none() {
+                diff.added.push((name.clone(), column_type.clone()));
+            }
+        }
+
+        diff
+    }
+
+    /// Checks `actual` against this expected schema
+    pub fn validate(&self, actual: &Schema) -> Result<(), SchemaDiff> {
+        let diff = self.diff(actual);
+        if diff.is_empty() {
+            Ok(())
+        } else {
+            Err(diff)
+        }
+    }
+}
+
+/// A change in a query's columns between two of its runs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaDrift {
+    pub key: String,
+    pub previous: Schema,
+    pub current: Schema,
+    pub diff: SchemaDiff,
+}
+
+/// Remembers the last schema seen for each scheduled or watched query and
+/// reports when it changes
+#[derive(Debug, Clone, Default)]
+pub struct SchemaTracker {
+    schemas: HashMap<String, Schema>,
+}
+
+impl SchemaTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn last_schema(&self, key: &str) -> Option<&Schema> {
+        self.schemas.get(key)
+    }
+
+    /// Records `schema` for `key`, returning the drift from the previous one if any.
+    /// The first observation of a key never drifts.
+    pub fn observe(&mut self, key: impl Into<String>, schema: Schema) -> Option<SchemaDrift> {
+        let key = key.into();
+        let previous = self.schemas.insert(key.clone(), schema.clone())?;
+        let diff = previous.diff(&schema);
+
+        if diff.is_empty() {
+            None
+        } else {
+            Some(SchemaDrift {
+                key,
+                previous,
+                current: schema,
+                diff,
+            })
+        }
+    }
+
+    /// Fetches the schema of a completed run and observes it
+    pub async fn observe_run(
+        &mut self,
+        flipside: &Flipside,
+        key: impl Into<String>,
+        query_run: &QueryRun,
+    ) -> Result<Option<SchemaDrift>, ApiError> {
+        let schema = flipside
+            .get_schema(query_run.sql_statement_id.clone())
+            .await?;
+        Ok(self.observe(key, schema))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transfers() -> Schema {
+        Schema::new()
+            .column("block_number", ColumnType::Number)
+            .column("tx_hash", ColumnType::String)
+            .column("amount", ColumnType::Number)
+    }
+
+    #[test]
+    fn diff_reports_added_removed_and_retyped_columns() {
+        let current = Schema::new()
+            .column("BLOCK_NUMBER", ColumnType::Number)
+            .column("amount", ColumnType::String)
+            .column("fee", ColumnType::Number);
+
+        let diff = transfers().diff(&current);
+        assert_eq!(diff.added, vec![("fee".to_string(), ColumnType::Number)]);
+        assert_eq!(
+            diff.removed,
+            vec![("tx_hash".to_string(), ColumnType::String)]
+        );
+        assert_eq!(
+            diff.retyped,
+            vec![("amount".to_string(), ColumnType::Number, ColumnType::String)]
+        );
+        assert_eq!(transfers().validate(&current), Err(diff));
+        assert_eq!(transfers().validate(&transfers()), Ok(()));
+    }
+
+    #[test]
+    fn from_metadata_reads_types_case_insensitively() {
+        let metadata = ColumnMetadata {
+            columns: vec!["block_number".to_string(), "tx_hash".to_string()],
+            types: vec!["NUMBER".to_string(), "mystery".to_string()],
+            col_type_map: HashMap::new(),
+        };
+        assert_eq!(
+            Schema::from_metadata(&metadata),
+            Schema::new()
+                .column("block_number", ColumnType::Number)
+                .column("tx_hash", ColumnType::Unknown)
+        );
+    }
+
+    #[test]
+    fn tracker_reports_drift_after_the_first_observation() {
+        let mut tracker = SchemaTracker::new();
+        assert_eq!(tracker.observe("transfers", transfers()), None);
+        assert_eq!(tracker.observe("transfers", transfers()), None);
+
+        let current = transfers().column("fee", ColumnType::Number);
+        let drift = tracker.observe("transfers", current.clone()).unwrap();
+        assert_eq!(drift.key, "transfers");
+        assert_eq!(drift.previous, transfers());
+        assert_eq!(
+            drift.diff.added,
+            vec![("fee".to_string(), ColumnType::Number)]
+        );
+        assert_eq!(tracker.last_schema("transfers"), Some(&current));
+        assert_eq!(tracker.observe("other", transfers()), None);
+    }
+}
@@ -0,0 +1,137 @@
+use crate::result_set::{normalize_row, ResultSet, Row};
+use crate::rpc::{ColumnType, GetQueryRunResultsResult};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Distinguishes the spill files of a process, so spilling the same run twice at
+/// once doesn't make both write to the same file
+static SPILL_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Results written to a temporary file, one JSON row per line.
+/// The file is removed when the value is dropped.
+#[derive(Debug)]
+pub struct SpilledResultSet {
+    pub column_names: Vec<String>,
+    pub column_types: Vec<ColumnType>,
+    len: usize,
+    path: PathBuf,
+}
+
+impl SpilledResultSet {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Reads the rows back from disk one at a time
+    pub fn rows(&self) -> io::Result<impl Iterator<Item = io::Result<Row>>> {
+        let reader = BufReader::new(File::open(&self.path)?);
+        Ok(reader.lines().map(|line| {
+            serde_json::from_str(&line?)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+        }))
+    }
+
+    /// Loads every row into memory
+    pub fn load(&self) -> io::Result<ResultSet> {
+        Ok(ResultSet {
+            column_names: self.column_names.clone(),
+            column_types: self.column_types.clone(),
+            rows: self.rows()?.collect::<io::Result<_>>()?,
+        })
+    }
+}
+
+impl Drop for SpilledResultSet {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+pub(crate) struct SpillWriter {
+    result_set: SpilledResultSet,
+    writer: BufWriter<File>,
+}
+
+impl SpillWriter {
+    pub(crate) fn create(query_run_id: &str) -> io::Result<Self> {
+        // `create_new` skips files left behind by a crashed process with the same id
+        let (path, file) = loop {
+            let path = std::env::temp_dir().join(format!(
+                "flipside-{}-{}-{}.ndjson",
+                query_run_id,
+                std::process::id(),
+                SPILL_COUNTER.fetch_add(1, Ordering::Relaxed)
+            ));
+            match File::create_new(&path) {
+                Ok(file) => break (path, file),
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(err),
+            }
+        };
+        let writer = BufWriter::new(file);
+
+        Ok(Self {
+            result_set: SpilledResultSet {
+                column_names: Vec::new(),
+                column_types: Vec::new(),
+                len: 0,
+                path,
+            },
+            writer,
+        })
+    }
+
+    pub(crate) fn append(&mut self, page: GetQueryRunResultsResult) -> io::Result<()> {
+        if self.result_set.column_names.is_empty() {
+            self.result_set.column_names = page.column_names;
+            self.result_set.column_types = page.column_types;
+        }
+
+        for row in page.rows {
+            serde_json::to_writer(
+                &mut self.writer,
+                &normalize_row(
+                    &self.result_set.column_names,
+                    &self.result_set.column_types,
+                    row,
+                ),
+            )?;
+            self.writer.write_all(b"\n")?;
+            self.result_set.len += 1;
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn finish(mut self) -> io::Result<SpilledResultSet> {
+        self.writer.flush()?;
+        Ok(self.result_set)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn concurrent_spills_of_a_run_use_distinct_files() {
+        let first = SpillWriter::create("run").unwrap().finish().unwrap();
+        let second = SpillWriter::create("run").unwrap().finish().unwrap();
+        assert_ne!(first.path(), second.path());
+
+        let path = first.path().to_path_buf();
+        drop(first);
+        assert!(!path.exists());
+        assert!(second.path().exists());
+    }
+}
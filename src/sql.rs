@@ -0,0 +1,199 @@
+use crate::flipside::Query;
+use flipside_sql::{Lexer, TokenKind};
+
+#[cfg(feature = "derive")]
+pub use flipside_derive::include_sql;
+
+/// A SQL file embedded with `include_sql!` (behind the `derive` feature)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SqlFile {
+    pub sql: &'static str,
+    /// Named `:placeholders`, in order of first appearance
+    pub params: &'static [&'static str],
+}
+
+impl SqlFile {
+    /// Query running the file, to be completed with `Query::bind`
+    pub fn query(&self) -> Query {
+        Query::new(self.sql)
+    }
+}
+
+/// Value bound to a named placeholder, rendered as a Snowflake SQL literal
+#[derive(Debug, Clone, PartialEq)]
+pub enum SqlValue {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+    /// Rendered as a parenthesized list, e.g. for `IN :values`. An empty list
+    /// renders as `(NULL)`, which matches nothing.
+    List(Vec<SqlValue>),
+}
+
+impl SqlValue {
+    pub fn to_sql(&self) -> String {
+        match self {
+            SqlValue::Null => "NULL".to_string(),
+            SqlValue::Bool(b) => b.to_string().to_uppercase(),
+            SqlValue::Int(i) => i.to_string(),
+            SqlValue::Float(f) if f.is_nan() => "'NaN'::FLOAT".to_string(),
+            SqlValue::Float(f) if f.is_infinite() => {
+                format!("'{}inf'::FLOAT", if *f < 0.0 { "-" } else { "" })
+            }
+            SqlValue::Float(f) => format!("{:?}", f),
+            SqlValue::String(s) => quote_literal(s),
+            SqlValue::List(values) if values.is_empty() => "(NULL)".to_string(),
+            SqlValue::List(values) => format!(
+                "({})",
+                values
+                    .iter()
+                    .map(SqlValue::to_sql)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+}
+
+impl From<bool> for SqlValue {
+    fn from(b: bool) -> Self {
+        SqlValue::Bool(b)
+    }
+}
+
+impl From<i32> for SqlValue {
+    fn from(i: i32) -> Self {
+        SqlValue::Int(i.into())
+    }
+}
+
+impl From<i64> for SqlValue {
+    fn from(i: i64) -> Self {
+        SqlValue::Int(i)
+    }
+}
+
+impl From<u32> for SqlValue {
+    fn from(i: u32) -> Self {
+        SqlValue::Int(i.into())
+    }
+}
+
+impl From<f64> for SqlValue {
+    fn from(f: f64) -> Self {
+        SqlValue::Float(f)
+    }
+}
+
+impl From<&str> for SqlValue {
+    fn from(s: &str) -> Self {
+        SqlValue::String(s.to_string())
+    }
+}
+
+impl From<String> for SqlValue {
+    fn from(s: String) -> Self {
+        SqlValue::String(s)
+    }
+}
+
+impl<T: Into<SqlValue>> From<Option<T>> for SqlValue {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(SqlValue::Null, Into::into)
+    }
+}
+
+impl<T: Into<SqlValue>> From<Vec<T>> for SqlValue {
+    fn from(values: Vec<T>) -> Self {
+        SqlValue::List(values.into_iter().map(Into::into).collect())
+    }
+}
+
+/// Quotes `s` as a string literal, escaping quotes and backslashes
+pub fn quote_literal(s: &str) -> String {
+    format!("'{}'", s.replace('\\', "\\\\").replace('\'', "''"))
+}
+
+/// Quotes `s` as an identifier. Quoted identifiers are case-sensitive in
+/// Snowflake, so unquoted names in the rest of the query must be upper-case to
+/// match.
+pub fn quote_ident(s: &str) -> String {
+    format!("\"{}\"", s.replace('"', "\"\""))
+}
+
+/// Quotes every part of a qualified name, e.g. `["db", "schema", "table"]`
+pub fn quote_qualified<'a>(parts: impl IntoIterator<Item = &'a str>) -> String {
+    parts
+        .into_iter()
+        .map(quote_ident)
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// Renders values as a parenthesized list of literals, e.g. for `IN (...)`. An
+/// empty list renders as `(NULL)`, which matches nothing, since `()` is invalid.
+pub fn quote_list<T: Into<SqlValue>>(values: impl IntoIterator<Item = T>) -> String {
+    SqlValue::List(values.into_iter().map(Into::into).collect()).to_sql()
+}
+
+/// Quotes identifiers as a comma-separated list, e.g. for a `SELECT` clause
+pub fn quote_ident_list<'a>(idents: impl IntoIterator<Item = &'a str>) -> String {
+    idents
+        .into_iter()
+        .map(quote_ident)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Replaces `:name` placeholders with what `lookup` returns for them, leaving the
+/// ones it returns `None` for. String literals, quoted identifiers and comments are
+/// skipped, as are `::` casts and `col:path` accesses of semi-structured columns,
+/// since a placeholder can't follow an identifier.
+pub(crate) fn replace_placeholders(
+    sql: &str,
+    mut lookup: impl FnMut(&str) -> Option<String>,
+) -> String {
+    let mut out = String::with_capacity(sql.len());
+    for token in Lexer::new(sql) {
+        match token.kind {
+            TokenKind::Placeholder => match lookup(&token.text[1..]) {
+                Some(value) => out.push_str(&value),
+                None => out.push_str(token.text),
+            },
+            _ => out.push_str(token.text),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_render_as_literals() {
+        assert_eq!(quote_literal(r"it's \"), r"'it''s \\'");
+        assert_eq!(quote_ident("a\"b"), "\"a\"\"b\"");
+        assert_eq!(quote_qualified(["db", "t"]), "\"db\".\"t\"");
+        assert_eq!(quote_list(Vec::<i64>::new()), "(NULL)");
+        assert_eq!(
+            SqlValue::from(vec![Some("a"), None]).to_sql(),
+            "('a', NULL)"
+        );
+        assert_eq!(SqlValue::Float(f64::NEG_INFINITY).to_sql(), "'-inf'::FLOAT");
+        assert_eq!(SqlValue::Float(1.0).to_sql(), "1.0");
+        assert_eq!(SqlValue::Bool(true).to_sql(), "TRUE");
+    }
+
+    #[test]
+    fn only_known_placeholders_are_replaced() {
+        let sql = "select ':a', x::int, v:a -- :a\nwhere a = :a and b = :b";
+        let replaced = replace_placeholders(sql, |name| (name == "a").then(|| "1".to_string()));
+        assert_eq!(
+            replaced,
+            "select ':a', x::int, v:a -- :a\nwhere a = 1 and b = :b"
+        );
+    }
+}
@@ -0,0 +1,259 @@
+use crate::defaults::PAGE_SIZE;
+use crate::error::ApiError;
+use crate::flipside::Flipside;
+use crate::result_set::{as_integer, ResultSet};
+use crate::rpc::ColumnType;
+use crate::sql::{quote_ident, quote_ident_list};
+use futures::stream::StreamExt;
+use rusqlite::types::Value as SqliteValue;
+use rusqlite::{params_from_iter, Connection};
+use serde_json::Value;
+use std::panic;
+use std::path::Path;
+use std::pin::pin;
+
+#[derive(Debug, thiserror::Error)]
+pub enum SqliteExportError {
+    #[error(transparent)]
+    RpcError(#[from] ApiError),
+    #[error(transparent)]
+    SqliteError(#[from] rusqlite::Error),
+    /// A value that doesn't fit its column's type, e.g. text in a number column
+    #[error("invalid value {value} in column `{column}`")]
+    InvalidValue { column: String, value: Value },
+    #[error("the export was cancelled")]
+    Cancelled,
+}
+
+/// SQLite column type of a result column. Dates stay ISO-8601 text, and objects,
+/// arrays and unknown types JSON text. Exports make number columns holding only
+/// integers `INTEGER` instead.
+pub fn sqlite_type(column_type: &ColumnType) -> &'static str {
+    match column_type {
+        ColumnType::Number => "REAL",
+        ColumnType::Boolean => "INTEGER",
+        ColumnType::Date
+        | ColumnType::String
+        | ColumnType::Object
+        | ColumnType::Array
+        | ColumnType::Unknown => "TEXT",
+    }
+}
+
+impl Flipside {
+    /// Copies the results of a run into `table` of the SQLite database at `db_path`,
+    /// creating the table from the first page's columns if it doesn't exist. Each
+    /// page is inserted in its own transaction, on a blocking thread. Integers are
+    /// stored as integers, and values that don't fit their column's type fail the
+    /// export. Returns the number of rows inserted.
+    pub async fn export_sqlite(
+        &self,
+        query_run_id: String,
+        db_path: impl AsRef<Path>,
+        table: &str,
+    ) -> Result<usize, SqliteExportError> {
+        let db_path = db_path.as_ref().to_path_buf();
+        let mut conn = blocking(move || Ok(Connection::open(db_path)?)).await?;
+        let mut pages = pin!(self.result_pages(query_run_id, PAGE_SIZE));
+        let mut insert = None;
+        let mut rows = 0;
+
+        while let Some(page) = pages.next().await {
+            let page = ResultSet::from(page?);
+            if page.column_names.is_empty() {
+                continue;
+            }
+
+            let table = table.to_string();
+            let previous_insert = insert.take();
+            let page_rows = page.rows.len();
+            let (page_conn, page_insert) = blocking(move || {
+                let insert = match previous_insert {
+                    Some(insert) => insert,
+                    None => create_table(&conn, &table, &page)?,
+                };
+                insert_rows(&mut conn, &insert, &page)?;
+                Ok((conn, insert))
+            })
+            .await?;
+
+            conn = page_conn;
+            insert = Some(page_insert);
+            rows += page_rows;
+        }
+
+        Ok(rows)
+    }
+}
+
+/// Runs SQLite calls on a blocking thread, off the async runtime's workers
+async fn blocking<T: Send + 'static>(
+    f: impl FnOnce() -> Result<T, SqliteExportError> + Send + 'static,
+) -> Result<T, SqliteExportError> {
+    match tokio::task::spawn_blocking(f).await {
+        Ok(res) => res,
+        Err(err) if err.is_panic() => panic::resume_unwind(err.into_panic()),
+        Err(_) => Err(SqliteExportError::Cancelled),
+    }
+}
+
+/// Creates the table and returns the statement inserting a row into it
+fn create_table(conn: &Connection, table: &str, page: &ResultSet) -> rusqlite::Result<String> {
+    let columns = page
+        .column_names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            let column_type = match page.column_types.get(i).unwrap_or(&ColumnType::Unknown) {
+                ColumnType::Number if page.is_integral(i) => "INTEGER",
+                column_type => sqlite_type(column_type),
+            };
+            format!("{} {}", quote_ident(name), column_type)
+        })
+        .collect::<Vec<_>>();
+    conn.execute(
+        &format!(
+            "CREATE TABLE IF NOT EXISTS {} ({})",
+            quote_ident(table),
+            columns.join(", ")
+        ),
+        [],
+    )?;
+
+    Ok(format!(
+        "INSERT INTO {} ({}) VALUES ({})",
+        quote_ident(table),
+        quote_ident_list(page.column_names.iter().map(String::as_str)),
+        vec!["?"; page.column_names.len()].join(", ")
+    ))
+}
+
+/// Inserts the rows of a page in one transaction
+fn insert_rows(
+    conn: &mut Connection,
+    insert: &str,
+    page: &ResultSet,
+) -> Result<(), SqliteExportError> {
+    let tx = conn.transaction()?;
+    {
+        let mut statement = tx.prepare_cached(insert)?;
+        for row in &page.rows {
+            let values = page
+                .column_types
+                .iter()
+                .enumerate()
+                .map(|(i, column_type)| {
+                    let value = row.get(i).unwrap_or(&Value::Null);
+                    sqlite_value(value, column_type).ok_or_else(|| {
+                        SqliteExportError::InvalidValue {
+                            column: page.column_names[i].clone(),
+                            value: value.clone(),
+                        }
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            statement.execute(params_from_iter(values))?;
+        }
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/// `value` converted for a column of `column_type`, `None` if it doesn't fit
+fn sqlite_value(value: &Value, column_type: &ColumnType) -> Option<SqliteValue> {
+    Some(match (value, column_type) {
+        (Value::Null, _) => SqliteValue::Null,
+        (value, ColumnType::Number) => match as_integer(value) {
+            Some(n) => SqliteValue::Integer(n),
+            None => SqliteValue::Real(match value {
+                Value::Number(n) => n.as_f64()?,
+                Value::String(s) => s.trim().parse().ok().filter(|n: &f64| n.is_finite())?,
+                _ => return None,
+            }),
+        },
+        (Value::Bool(b), ColumnType::Boolean) => SqliteValue::Integer(*b as i64),
+        (Value::String(s), ColumnType::Boolean) => {
+            SqliteValue::Integer(s.to_lowercase().parse::<bool>().ok()? as i64)
+        }
+        (_, ColumnType::Boolean) => return None,
+        (Value::String(s), _) => SqliteValue::Text(s.clone()),
+        (value, _) => SqliteValue::Text(value.to_string()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn page(rows: Vec<Vec<Value>>) -> ResultSet {
+        ResultSet {
+            column_names: vec!["id".to_string(), "amount".to_string(), "ok".to_string()],
+            column_types: vec![ColumnType::Number, ColumnType::Number, ColumnType::Boolean],
+            rows,
+        }
+    }
+
+    #[test]
+    fn integers_are_stored_exactly() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        let page = page(vec![
+            vec![json!(9007199254740993i64), json!(1.5), json!(true)],
+            vec![json!("2"), json!("2.5"), json!("false")],
+            vec![Value::Null, Value::Null, Value::Null],
+        ]);
+        let insert = create_table(&conn, "t", &page).unwrap();
+        insert_rows(&mut conn, &insert, &page).unwrap();
+
+        let columns = conn
+            .prepare("SELECT type FROM pragma_table_info('t')")
+            .unwrap()
+            .query_map([], |row| row.get::<_, String>(0))
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(columns, ["INTEGER", "REAL", "INTEGER"]);
+
+        let rows = conn
+            .prepare("SELECT id, amount, ok FROM t")
+            .unwrap()
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, Option<i64>>(0)?,
+                    row.get::<_, Option<f64>>(1)?,
+                    row.get::<_, Option<bool>>(2)?,
+                ))
+            })
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(
+            rows,
+            [
+                (Some(9007199254740993), Some(1.5), Some(true)),
+                (Some(2), Some(2.5), Some(false)),
+                (None, None, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn values_that_do_not_fit_fail() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        let page = page(vec![
+            vec![json!(1), json!(1), json!(true)],
+            vec![json!(2), json!("n/a"), json!(true)],
+        ]);
+        let insert = create_table(&conn, "t", &page).unwrap();
+        let err = insert_rows(&mut conn, &insert, &page).unwrap_err();
+        assert!(matches!(
+            err,
+            SqliteExportError::InvalidValue { column, value } if column == "amount" && value == json!("n/a")
+        ));
+
+        let count: i64 = conn
+            .query_row("SELECT count(*) FROM t", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 0);
+    }
+}
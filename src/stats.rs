@@ -0,0 +1,192 @@
+use crate::rpc::QueryRun;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Timings and size of a query run, derived from its timestamps. Phases the run
+/// hasn't reached yet are `None`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct QueryRunStats {
+    /// From creation until execution started
+    pub queued: Option<Duration>,
+    pub execution: Option<Duration>,
+    /// From the end of execution until results were written
+    pub streaming: Option<Duration>,
+    /// From creation until the run ended
+    pub elapsed: Option<Duration>,
+    pub bytes: Option<u64>,
+    pub row_count: Option<usize>,
+}
+
+impl QueryRunStats {
+    pub fn queued_seconds(&self) -> Option<f64> {
+        self.queued.map(|d| d.as_secs_f64())
+    }
+
+    pub fn execution_seconds(&self) -> Option<f64> {
+        self.execution.map(|d| d.as_secs_f64())
+    }
+
+    pub fn streaming_seconds(&self) -> Option<f64> {
+        self.streaming.map(|d| d.as_secs_f64())
+    }
+
+    pub fn elapsed_seconds(&self) -> Option<f64> {
+        self.elapsed.map(|d| d.as_secs_f64())
+    }
+}
+
+impl QueryRun {
+    pub fn stats(&self) -> QueryRunStats {
+        let created_at = parse_timestamp(&self.created_at);
+        let started_at = self.started_at.as_deref().and_then(parse_timestamp);
+        let running_ended_at = self
+            .query_running_ended_at
+            .as_deref()
+            .and_then(parse_timestamp);
+        let streaming_ended_at = self
+            .query_streaming_ended_at
+            .as_deref()
+            .and_then(parse_timestamp);
+        let ended_at = self.ended_at.as_deref().and_then(parse_timestamp);
+
+        QueryRunStats {
+            queued: between(created_at, started_at),
+            execution: between(started_at, running_ended_at),
+            streaming: between(running_ended_at, streaming_ended_at),
+            elapsed: between(created_at, ended_at),
+            bytes: self
+                .total_size
+                .as_deref()
+                .and_then(|size| size.parse().ok()),
+            row_count: self.row_count,
+        }
+    }
+}
+
+fn between(start: Option<f64>, end: Option<f64>) -> Option<Duration> {
+    Some(Duration::from_secs_f64((end? - start?).max(0.0)))
+}
+
+/// Seconds since the Unix epoch of an RFC 3339 timestamp, e.g.
+/// `2024-01-01T12:00:00.123Z` or `2024-01-01 12:00:00+02:00`
+pub(crate) fn parse_timestamp(s: &str) -> Option<f64> {
+    let s = s.trim();
+    let number = |range: std::ops::Range<usize>| s.get(range)?.parse::<i64>().ok();
+
+    let (year, month, day) = (number(0..4)?, number(5..7)?, number(8..10)?);
+    let (hour, minute, second) = (number(11..13)?, number(14..16)?, number(17..19)?);
+    if !matches!(s.as_bytes().get(10), Some(b'T' | b't' | b' ')) {
+        return None;
+    }
+
+    let rest = &s[19..];
+    let fraction_len = rest
+        .strip_prefix('.')
+        .map(|fraction| fraction.bytes().take_while(u8::is_ascii_digit).count() + 1)
+        .unwrap_or(0);
+    let fraction = if fraction_len > 1 {
+        format!("0{}", &rest[..fraction_len]).parse::<f64>().ok()?
+    } else {
+        0.0
+    };
+
+    let offset = match &rest[fraction_len..] {
+        "" | "Z" | "z" => 0,
+        offset => {
+            let sign = match offset.as_bytes()[0] {
+                b'+' => 1,
+                b'-' => -1,
+                _ => return None,
+            };
+            let hours = offset.get(1..3)?.parse::<i64>().ok()?;
+            let minutes = offset
+                .get(3..)
+                .map(|m| m.trim_start_matches(':'))
+                .filter(|m| !m.is_empty())
+                .map(|m| m.parse::<i64>().ok())
+                .unwrap_or(Some(0))?;
+            sign * (hours * 3600 + minutes * 60)
+        }
+    };
+
+    // Days since the epoch, from Howard Hinnant's `days_from_civil`
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+
+    Some((days * 86400 + hour * 3600 + minute * 60 + second - offset) as f64 + fraction)
+}
+
+/// RFC 3339 UTC timestamp with milliseconds, e.g. `2024-01-01T12:00:00.123Z`.
+/// Times before the epoch are clamped to it.
+pub(crate) fn format_timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs() as i64;
+    let (days, time_of_day) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+
+    // Civil date, from Howard Hinnant's `civil_from_days`
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        time_of_day / 3600,
+        time_of_day % 3600 / 60,
+        time_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_rfc_3339_timestamps() {
+        assert_eq!(parse_timestamp("1970-01-01T00:00:00Z"), Some(0.0));
+        assert_eq!(
+            parse_timestamp("2024-01-01T00:00:00.500Z"),
+            Some(1_704_067_200.5)
+        );
+        assert_eq!(
+            parse_timestamp("2024-01-01 02:00:00+02:00"),
+            Some(1_704_067_200.0)
+        );
+        assert_eq!(
+            parse_timestamp("2024-02-29t00:00:00-0130"),
+            Some(1_709_170_200.0)
+        );
+        assert_eq!(parse_timestamp("2024-01-01"), None);
+        assert_eq!(parse_timestamp("2024-01-01X00:00:00"), None);
+    }
+
+    #[test]
+    fn formats_timestamps_that_parse_back() {
+        for millis in [0, 951_782_400_000, 1_704_067_200_500, 4_102_444_799_999] {
+            let time = UNIX_EPOCH + Duration::from_millis(millis);
+            let formatted = format_timestamp(time);
+            assert_eq!(
+                (parse_timestamp(&formatted).unwrap() * 1000.0).round() as u64,
+                millis,
+                "{}",
+                formatted
+            );
+        }
+        assert_eq!(
+            format_timestamp(UNIX_EPOCH + Duration::from_millis(951_782_400_000)),
+            "2000-02-29T00:00:00.000Z"
+        );
+    }
+}
@@ -0,0 +1,139 @@
+use futures::stream::{self, Stream, StreamExt, TryStreamExt};
+use std::future;
+
+/// Adapters for streams of fallible rows. Errors are passed through unchanged and
+/// rows are only pulled from the underlying stream as the adapters are polled.
+pub trait RowStreamExt<T, E>: Stream<Item = Result<T, E>> + Sized {
+    fn map_rows<U, F>(self, f: F) -> impl Stream<Item = Result<U, E>>
+    where
+        F: FnMut(T) -> U,
+    {
+        self.map_ok(f)
+    }
+
+    /// Like `map_rows`, with a fallible mapping whose error converts into the stream's
+    fn try_map_rows<U, F, E2>(self, mut f: F) -> impl Stream<Item = Result<U, E>>
+    where
+        F: FnMut(T) -> Result<U, E2>,
+        E2: Into<E>,
+    {
+        self.map(move |row| row.and_then(|row| f(row).map_err(Into::into)))
+    }
+
+    fn filter_rows<F>(self, mut predicate: F) -> impl Stream<Item = Result<T, E>>
+    where
+        F: FnMut(&T) -> bool,
+    {
+        self.try_filter(move |row| future::ready(predicate(row)))
+    }
+
+    /// Groups rows into chunks of at most `size`. Rows buffered before an error are
+    /// yielded as a chunk ahead of the error.
+    fn chunk_rows(self, size: usize) -> impl Stream<Item = Result<Vec<T>, E>> {
+        let size = size.max(1);
+
+        stream::unfold(
+            (Box::pin(self), Vec::with_capacity(size), None, false),
+            move |(mut rows, mut chunk, mut error, mut done)| async move {
+                if let Some(error) = error.take() {
+                    return Some((Err(error), (rows, chunk, None, done)));
+                }
+
+                while !done && chunk.len() < size {
+                    match rows.next().await {
+                        Some(Ok(row)) => chunk.push(row),
+                        Some(Err(err)) if chunk.is_empty() => {
+                            return Some((Err(err), (rows, chunk, None, done)));
+                        }
+                        Some(Err(err)) => {
+                            error = Some(err);
+                            break;
+                        }
+                        None => done = true,
+                    }
+                }
+
+                if chunk.is_empty() {
+                    return None;
+                }
+
+                let full = std::mem::replace(&mut chunk, Vec::with_capacity(size));
+                Some((Ok(full), (rows, chunk, error, done)))
+            },
+        )
+    }
+}
+
+impl<S, T, E> RowStreamExt<T, E> for S where S: Stream<Item = Result<T, E>> + Sized {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+
+    fn rows(items: Vec<Result<u32, String>>) -> impl Stream<Item = Result<u32, String>> {
+        stream::iter(items)
+    }
+
+    #[test]
+    fn chunk_rows_yields_buffered_rows_before_an_error() {
+        let chunks = rows(vec![
+            Ok(1),
+            Ok(2),
+            Ok(3),
+            Err("lost connection".to_string()),
+            Err("again".to_string()),
+            Ok(4),
+        ])
+        .chunk_rows(2);
+
+        assert_eq!(
+            block_on(chunks.collect::<Vec<_>>()),
+            vec![
+                Ok(vec![1, 2]),
+                Ok(vec![3]),
+                Err("lost connection".to_string()),
+                Err("again".to_string()),
+                Ok(vec![4]),
+            ]
+        );
+    }
+
+    #[test]
+    fn chunk_rows_of_zero_are_single_rows() {
+        let chunks = rows(vec![Ok(1), Ok(2)]).chunk_rows(0);
+        assert_eq!(
+            block_on(chunks.collect::<Vec<_>>()),
+            vec![Ok(vec![1]), Ok(vec![2])]
+        );
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Odd(u32);
+
+    impl From<Odd> for String {
+        fn from(Odd(n): Odd) -> Self {
+            format!("{} is odd", n)
+        }
+    }
+
+    #[test]
+    fn try_map_rows_converts_mapping_errors() {
+        let halves = rows(vec![Ok(4), Ok(3), Err("upstream".to_string())])
+            .try_map_rows(|n| match n % 2 {
+                0 => Ok(n / 2),
+                _ => Err(Odd(n)),
+            })
+            .filter_rows(|&n| n > 0)
+            .map_rows(|n| n * 10);
+
+        assert_eq!(
+            block_on(halves.collect::<Vec<_>>()),
+            vec![
+                Ok(20),
+                Err("3 is odd".to_string()),
+                Err("upstream".to_string())
+            ]
+        );
+    }
+}
@@ -0,0 +1,179 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// The Flipside API doesn't document limits on tags. These are the SDK's own,
+// chosen to keep tags from bloating every request and response they travel in,
+// and well above what the official SDKs send (`sdk_package`, `sdk_version`...).
+pub const MAX_TAGS: usize = 32;
+pub const MAX_TAG_KEY_LENGTH: usize = 64;
+pub const MAX_TAG_VALUE_LENGTH: usize = 256;
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum TagError {
+    #[error("tag key is empty")]
+    EmptyKey,
+    #[error("tag key `{0}` is longer than {MAX_TAG_KEY_LENGTH} bytes")]
+    KeyTooLong(String),
+    #[error("value of tag `{0}` is longer than {MAX_TAG_VALUE_LENGTH} bytes")]
+    ValueTooLong(String),
+    #[error("tag key `{0}` contains invalid characters")]
+    InvalidKey(String),
+    #[error("value of tag `{0}` contains control characters")]
+    InvalidValue(String),
+    #[error("more than {MAX_TAGS} tags")]
+    TooManyTags,
+}
+
+/// Tags attached to query runs. Keys may only contain ASCII alphanumerics and
+/// `_-.:`, values may not contain control characters, and both are length-limited.
+/// Tags deserialized from API responses are taken as they are, since the server
+/// doesn't enforce these limits and other clients may not follow them.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openrpc", derive(schemars::JsonSchema))]
+#[serde(transparent)]
+pub struct Tags(HashMap<String, Option<String>>);
+
+fn validate(key: &str, value: &str) -> Result<(), TagError> {
+    if key.is_empty() {
+        return Err(TagError::EmptyKey);
+    }
+    if key.len() > MAX_TAG_KEY_LENGTH {
+        return Err(TagError::KeyTooLong(key.to_string()));
+    }
+    if !key
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || "_-.:".contains(c))
+    {
+        return Err(TagError::InvalidKey(key.to_string()));
+    }
+    if value.len() > MAX_TAG_VALUE_LENGTH {
+        return Err(TagError::ValueTooLong(key.to_string()));
+    }
+    if value.chars().any(char::is_control) {
+        return Err(TagError::InvalidValue(key.to_string()));
+    }
+    Ok(())
+}
+
+impl Tags {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(
+        &mut self,
+        key: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Result<(), TagError> {
+        let key = key.into();
+        let value = value.into();
+
+        validate(&key, &value)?;
+        if self.0.len() >= MAX_TAGS && !self.0.contains_key(&key) {
+            return Err(TagError::TooManyTags);
+        }
+
+        self.0.insert(key, Some(value));
+        Ok(())
+    }
+
+    pub fn with(
+        mut self,
+        key: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Result<Self, TagError> {
+        self.insert(key, value)?;
+        Ok(self)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.as_deref())
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        self.0.remove(key).flatten()
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.0.contains_key(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, Option<&str>)> {
+        self.0
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_deref()))
+    }
+}
+
+/// Builds validated `Tags`, returning a `Result<Tags, TagError>`
+///
+/// ```
+/// let tags = flipside_sdk::tags! { "env" => "prod", "team" => "growth" }.unwrap();
+/// assert_eq!(tags.get("env"), Some("prod"));
+/// ```
+#[macro_export]
+macro_rules! tags {
+    ($($key:expr => $value:expr),* $(,)?) => {
+        (|| -> ::std::result::Result<$crate::tags::Tags, $crate::tags::TagError> {
+            #[allow(unused_mut)]
+            let mut tags = $crate::tags::Tags::new();
+            $(tags.insert($key, $value)?;)*
+            Ok(tags)
+        })()
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn insert_validates_keys_and_values() {
+        let mut tags = Tags::new();
+        assert_eq!(tags.insert("", "x"), Err(TagError::EmptyKey));
+        assert_eq!(
+            tags.insert("a b", "x"),
+            Err(TagError::InvalidKey("a b".to_string()))
+        );
+        assert_eq!(
+            tags.insert("k".repeat(MAX_TAG_KEY_LENGTH + 1), "x"),
+            Err(TagError::KeyTooLong("k".repeat(MAX_TAG_KEY_LENGTH + 1)))
+        );
+        assert_eq!(
+            tags.insert("env", "a\nb"),
+            Err(TagError::InvalidValue("env".to_string()))
+        );
+
+        for i in 0..MAX_TAGS {
+            tags.insert(format!("tag{}", i), "x").unwrap();
+        }
+        assert_eq!(tags.insert("one_more", "x"), Err(TagError::TooManyTags));
+        tags.insert("tag0", "replaced").unwrap();
+    }
+
+    #[test]
+    fn deserialization_is_lenient() {
+        let tags: Tags = serde_json::from_value(json!({"env": "prod", "empty": null})).unwrap();
+        assert_eq!(tags.get("env"), Some("prod"));
+        assert!(tags.contains_key("empty"));
+        assert_eq!(
+            serde_json::to_value(&tags).unwrap(),
+            json!({"env": "prod", "empty": null})
+        );
+
+        let set_elsewhere: Tags = serde_json::from_value(
+            json!({"bad key": "x", "env": "v".repeat(MAX_TAG_VALUE_LENGTH + 1)}),
+        )
+        .unwrap();
+        assert_eq!(set_elsewhere.get("bad key"), Some("x"));
+    }
+}
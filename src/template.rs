@@ -0,0 +1,71 @@
+use crate::flipside::Query;
+use crate::sql::{quote_ident, SqlValue};
+use minijinja::value::{Value, ValueKind};
+use minijinja::{Environment, ErrorKind, UndefinedBehavior};
+use serde::Serialize;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+#[derive(Debug, thiserror::Error)]
+pub enum TemplateError {
+    #[error("failed to read the template")]
+    IoError(#[source] io::Error),
+    #[error("failed to render the template")]
+    RenderError(#[source] minijinja::Error),
+}
+
+impl Query {
+    /// Renders `template` with minijinja. Variables are inserted as is, so pass
+    /// values through the `literal` filter (`{{ addr | literal }}`) and names
+    /// through `ident` to quote them. Undefined variables are an error.
+    pub fn from_template(template: &str, ctx: impl Serialize) -> Result<Self, TemplateError> {
+        let sql = environment()
+            .render_str(template, ctx)
+            .map_err(TemplateError::RenderError)?;
+        Ok(Query::new(sql))
+    }
+
+    /// Like `from_template`, reading the template from `path`
+    pub fn from_template_file(
+        path: impl AsRef<Path>,
+        ctx: impl Serialize,
+    ) -> Result<Self, TemplateError> {
+        let template = fs::read_to_string(path).map_err(TemplateError::IoError)?;
+        Self::from_template(&template, ctx)
+    }
+}
+
+fn environment() -> Environment<'static> {
+    let mut env = Environment::new();
+    env.set_undefined_behavior(UndefinedBehavior::Strict);
+    env.add_filter("literal", |value: Value| {
+        sql_value(&value).map(|value| value.to_sql())
+    });
+    env.add_filter("ident", |name: &str| quote_ident(name));
+    env
+}
+
+fn sql_value(value: &Value) -> Result<SqlValue, minijinja::Error> {
+    Ok(match value.kind() {
+        ValueKind::Undefined | ValueKind::None => SqlValue::Null,
+        ValueKind::Bool => SqlValue::Bool(value.is_true()),
+        ValueKind::Number => match i64::try_from(value.clone()) {
+            Ok(i) => SqlValue::Int(i),
+            Err(_) => SqlValue::Float(f64::try_from(value.clone())?),
+        },
+        ValueKind::String => SqlValue::String(value.as_str().unwrap_or_default().to_string()),
+        ValueKind::Seq | ValueKind::Iterable => SqlValue::List(
+            value
+                .try_iter()?
+                .map(|value| sql_value(&value))
+                .collect::<Result<_, _>>()?,
+        ),
+        kind => {
+            return Err(minijinja::Error::new(
+                ErrorKind::InvalidOperation,
+                format!("{} can't be rendered as a SQL literal", kind),
+            ))
+        }
+    })
+}
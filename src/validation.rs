@@ -0,0 +1,151 @@
+use crate::result_set::{ResultSet, Row};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationIssue {
+    pub column: Option<String>,
+    pub reason: String,
+}
+
+impl ValidationIssue {
+    pub fn new(reason: impl Into<String>) -> Self {
+        Self {
+            column: None,
+            reason: reason.into(),
+        }
+    }
+
+    pub fn column(column: impl Into<String>, reason: impl Into<String>) -> Self {
+        Self {
+            column: Some(column.into()),
+            reason: reason.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RowIssue {
+    pub row: usize,
+    pub column: Option<String>,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ValidationReport {
+    pub rows_checked: usize,
+    pub issues: Vec<RowIssue>,
+}
+
+impl ValidationReport {
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+type Validator = Box<dyn Fn(&Row) -> Result<(), ValidationIssue> + Send + Sync>;
+
+/// Row checks run while results are materialized, collecting issues instead of
+/// stopping at the first one
+#[derive(Default)]
+pub struct Validators(Vec<Validator>);
+
+impl Validators {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(
+        mut self,
+        validator: impl Fn(&Row) -> Result<(), ValidationIssue> + Send + Sync + 'static,
+    ) -> Self {
+        self.0.push(Box::new(validator));
+        self
+    }
+
+    pub fn validate_row(&self, index: usize, row: &Row, report: &mut ValidationReport) {
+        report.rows_checked += 1;
+        for validator in &self.0 {
+            if let Err(issue) = validator(row) {
+                report.issues.push(RowIssue {
+                    row: index,
+                    column: issue.column,
+                    reason: issue.reason,
+                });
+            }
+        }
+    }
+
+    pub fn validate(&self, result_set: &ResultSet) -> ValidationReport {
+        let mut report = ValidationReport::default();
+        for (index, row) in result_set.rows.iter().enumerate() {
+            self.validate_row(index, row, &mut report);
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rpc::ColumnType;
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    #[test]
+    fn issues_are_collected_for_every_row_and_validator() {
+        let transfers = ResultSet {
+            column_names: vec!["from".to_string(), "amount".to_string()],
+            column_types: vec![ColumnType::String, ColumnType::Number],
+            rows: vec![
+                vec![json!("0xa"), json!(10)],
+                vec![Value::Null, json!(-1)],
+                vec![json!("0xb"), json!(5)],
+            ],
+        };
+        let validators = Validators::new()
+            .register(|row| match row[0].is_null() {
+                true => Err(ValidationIssue::column("from", "missing sender")),
+                false => Ok(()),
+            })
+            .register(|row| match row[1].as_i64() {
+                Some(amount) if amount < 0 => Err(ValidationIssue::new("negative amount")),
+                _ => Ok(()),
+            });
+
+        let report = validators.validate(&transfers);
+        assert_eq!(report.rows_checked, 3);
+        assert!(!report.is_ok());
+        assert_eq!(
+            report.issues,
+            vec![
+                RowIssue {
+                    row: 1,
+                    column: Some("from".to_string()),
+                    reason: "missing sender".to_string(),
+                },
+                RowIssue {
+                    row: 1,
+                    column: None,
+                    reason: "negative amount".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn validate_row_adds_to_an_existing_report() {
+        let validators = Validators::new().register(|_| Err(ValidationIssue::new("bad row")));
+        let mut report = ValidationReport::default();
+        validators.validate_row(4, &vec![json!(1)], &mut report);
+        validators.validate_row(5, &vec![json!(2)], &mut report);
+
+        assert_eq!(report.rows_checked, 2);
+        assert_eq!(
+            report
+                .issues
+                .iter()
+                .map(|issue| issue.row)
+                .collect::<Vec<_>>(),
+            vec![4, 5]
+        );
+    }
+}
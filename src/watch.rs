@@ -0,0 +1,95 @@
+use crate::registry::{QueryRegistry, RegistryError};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use tokio::task::JoinHandle;
+
+/// A filesystem registry that is reloaded when its directory changes, meant for
+/// development. Changes are detected by polling file modification times; if a
+/// reload fails (e.g. a half-written manifest) the previous registry is kept.
+pub struct WatchedRegistry {
+    pub(crate) registry: Arc<RwLock<Arc<QueryRegistry>>>,
+    task: JoinHandle<()>,
+}
+
+impl WatchedRegistry {
+    /// Loads the registry and starts polling `dir` every `interval`. `on_reload` is
+    /// called with the names of the queries that were added, removed or edited, so
+    /// callers can invalidate anything they cached for them.
+    pub fn watch(
+        dir: impl Into<PathBuf>,
+        interval: Duration,
+        on_reload: impl Fn(&[String]) + Send + Sync + 'static,
+    ) -> Result<Self, RegistryError> {
+        let dir = dir.into();
+        let registry = Arc::new(RwLock::new(Arc::new(QueryRegistry::load(&dir)?)));
+        let mut snapshot = dir_snapshot(&dir);
+
+        let task = tokio::spawn({
+            let registry = registry.clone();
+            async move {
+                let mut ticker = tokio::time::interval(interval);
+                loop {
+                    ticker.tick().await;
+
+                    let current = dir_snapshot(&dir);
+                    if current == snapshot {
+                        continue;
+                    }
+                    snapshot = current;
+
+                    let Ok(reloaded) = QueryRegistry::load(&dir) else {
+                        continue;
+                    };
+                    let previous =
+                        std::mem::replace(&mut *registry.write().unwrap(), Arc::new(reloaded));
+                    let changed = changed_queries(&previous, &registry.read().unwrap());
+                    if !changed.is_empty() {
+                        on_reload(&changed);
+                    }
+                }
+            }
+        });
+
+        Ok(Self { registry, task })
+    }
+
+    /// The registry as of the last successful reload
+    pub fn current(&self) -> Arc<QueryRegistry> {
+        self.registry.read().unwrap().clone()
+    }
+}
+
+impl Drop for WatchedRegistry {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+fn dir_snapshot(dir: &Path) -> BTreeMap<PathBuf, (Option<SystemTime>, u64)> {
+    fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            Some((entry.path(), (metadata.modified().ok(), metadata.len())))
+        })
+        .collect()
+}
+
+fn changed_queries(previous: &QueryRegistry, current: &QueryRegistry) -> Vec<String> {
+    let mut changed = current
+        .names()
+        .filter(|name| {
+            // A query whose refs stop resolving counts as changed
+            previous.hash(name).ok() != current.hash(name).ok()
+        })
+        .chain(previous.names().filter(|name| current.get(name).is_none()))
+        .map(String::from)
+        .collect::<Vec<_>>();
+    changed.sort();
+    changed
+}
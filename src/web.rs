@@ -0,0 +1,166 @@
+use crate::dedup::RecentRuns;
+use crate::defaults::{PAGE_NUMBER, PAGE_SIZE};
+use crate::error::ApiError as RpcError;
+use crate::flipside::{Flipside, QueryRunError};
+use crate::registry::{QueryRegistry, RegistryError};
+use crate::result_set::Fnv64;
+use crate::rpc::{ColumnType, Pagination, PaginationDetails};
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Query string parameters reserved for pagination, everything else is passed
+/// to the query as a parameter
+pub const PAGE_PARAM: &str = "page";
+pub const PAGE_SIZE_PARAM: &str = "page_size";
+
+/// How long a run serves the pages of requests with the same query and parameters
+pub const RUN_REUSE_WINDOW: Duration = Duration::from_secs(600);
+
+#[derive(Clone)]
+pub struct RegistryState {
+    pub flipside: Flipside,
+    pub registry: Arc<QueryRegistry>,
+    /// How long a run serves the pages of requests with the same query and
+    /// parameters before a new one is started
+    pub run_reuse_window: Duration,
+    runs: RecentRuns,
+}
+
+impl RegistryState {
+    pub fn new(flipside: Flipside, registry: Arc<QueryRegistry>) -> Self {
+        Self {
+            flipside,
+            registry,
+            run_reuse_window: RUN_REUSE_WINDOW,
+            runs: RecentRuns::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryResponse {
+    pub query_run_id: String,
+    pub column_names: Vec<String>,
+    pub column_types: Vec<ColumnType>,
+    pub rows: Vec<Value>,
+    pub page: PaginationDetails,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum WebError {
+    #[error(transparent)]
+    Registry(#[from] RegistryError),
+    #[error(transparent)]
+    Rpc(#[from] RpcError),
+    #[error("invalid pagination parameter {0}")]
+    InvalidPagination(String),
+}
+
+impl IntoResponse for WebError {
+    fn into_response(self) -> Response {
+        let status = match &self {
+            WebError::Registry(RegistryError::UnknownQuery(_)) => StatusCode::NOT_FOUND,
+            WebError::Registry(
+                RegistryError::MissingParam { .. } | RegistryError::InvalidParam { .. },
+            )
+            | WebError::InvalidPagination(_) => StatusCode::BAD_REQUEST,
+            WebError::Registry(RegistryError::QueryRunError(QueryRunError::Timeout { .. })) => {
+                StatusCode::GATEWAY_TIMEOUT
+            }
+            _ => StatusCode::BAD_GATEWAY,
+        };
+
+        (
+            status,
+            Json(serde_json::json!({ "error": self.to_string() })),
+        )
+            .into_response()
+    }
+}
+
+/// Serves every registered query at `GET /{name}`. The query string carries the
+/// query's parameters, which must be declared with types in the manifest, plus
+/// optional `page` and `page_size`. Requests with the same query and parameters
+/// within `RUN_REUSE_WINDOW` are served from the same run, so every page comes
+/// from one execution.
+pub fn registry_router(flipside: Flipside, registry: Arc<QueryRegistry>) -> Router {
+    Router::new()
+        .route("/{name}", get(run_registered_query))
+        .with_state(RegistryState::new(flipside, registry))
+}
+
+pub async fn run_registered_query(
+    State(state): State<RegistryState>,
+    Path(name): Path<String>,
+    Query(mut params): Query<HashMap<String, String>>,
+) -> Result<Json<QueryResponse>, WebError> {
+    let pagination = Pagination {
+        number: take_usize(&mut params, PAGE_PARAM)?.unwrap_or(PAGE_NUMBER),
+        size: take_usize(&mut params, PAGE_SIZE_PARAM)?.unwrap_or(PAGE_SIZE),
+    };
+    let mut sorted_params = params.iter().collect::<Vec<_>>();
+    sorted_params.sort();
+    let mut hasher = Fnv64::default();
+    hasher.write_value(&name);
+    hasher.write_value(&sorted_params);
+
+    let (query_run_id, _) = state
+        .runs
+        .entry(hasher.finish(), state.run_reuse_window)
+        .get_or_try_init(|| async {
+            let params = params
+                .iter()
+                .map(|(key, value)| (key.clone(), Value::String(value.clone())))
+                .collect();
+            let query = state
+                .registry
+                .render_typed(&name, &params)
+                .map_err(WebError::Registry)?;
+            let query_run = state
+                .flipside
+                .run(query)
+                .await
+                .map_err(|err| WebError::Registry(RegistryError::QueryRunError(err)))?;
+            Ok::<_, WebError>((query_run.id, Instant::now()))
+        })
+        .await?
+        .clone();
+    let results = state
+        .flipside
+        .get_query_results(
+            query_run_id.clone(),
+            Some(pagination),
+            Vec::new(),
+            Vec::new(),
+        )
+        .await
+        .map_err(WebError::Rpc)?;
+
+    Ok(Json(QueryResponse {
+        query_run_id,
+        column_names: results.column_names,
+        column_types: results.column_types,
+        rows: results.rows,
+        page: results.page,
+    }))
+}
+
+fn take_usize(params: &mut HashMap<String, String>, key: &str) -> Result<Option<usize>, WebError> {
+    params
+        .remove(key)
+        .map(|value| {
+            value
+                .parse()
+                .map_err(|_| WebError::InvalidPagination(format!("{}={}", key, value)))
+        })
+        .transpose()
+}
@@ -0,0 +1,83 @@
+#![cfg(feature = "derive")]
+
+use flipside_sdk::result_set::ResultSet;
+use flipside_sdk::row::{FromQueryRow, RowError};
+use flipside_sdk::rpc::ColumnType;
+use serde_json::{json, Value};
+
+#[derive(Debug, PartialEq, FromQueryRow)]
+struct Transfer {
+    #[query_row(rename = "TX_HASH")]
+    hash: String,
+    amount: f64,
+    block_number: u64,
+    #[query_row(default)]
+    memo: String,
+    #[query_row(default)]
+    fee: Option<u64>,
+}
+
+fn result_set(rows: Vec<Vec<Value>>) -> ResultSet {
+    ResultSet {
+        column_names: ["TX_HASH", "AMOUNT", "block_number", "memo"]
+            .map(String::from)
+            .to_vec(),
+        column_types: vec![
+            ColumnType::String,
+            ColumnType::Number,
+            ColumnType::Number,
+            ColumnType::String,
+        ],
+        rows,
+    }
+}
+
+#[test]
+fn rows_convert_through_renamed_defaulted_and_string_columns() {
+    let transfers = result_set(vec![
+        vec![json!("0xab"), json!(1.5), json!("17000000"), Value::Null],
+        vec![json!("0xcd"), json!("2"), json!(17000001), json!("gm")],
+    ])
+    .rows_into::<Transfer>()
+    .unwrap();
+
+    assert_eq!(
+        transfers,
+        [
+            Transfer {
+                hash: "0xab".to_string(),
+                amount: 1.5,
+                block_number: 17000000,
+                memo: String::new(),
+                fee: None,
+            },
+            Transfer {
+                hash: "0xcd".to_string(),
+                amount: 2.0,
+                block_number: 17000001,
+                memo: "gm".to_string(),
+                fee: None,
+            },
+        ]
+    );
+}
+
+#[test]
+fn missing_and_invalid_columns_fail() {
+    let column_names = ["TX_HASH".to_string()];
+    assert!(matches!(
+        Transfer::from_row(&column_names, &[json!("0xab")]),
+        Err(RowError::MissingColumn(column)) if column == "amount"
+    ));
+
+    let invalid = result_set(vec![vec![
+        json!("0xab"),
+        json!("not a number"),
+        json!(1),
+        Value::Null,
+    ]]);
+    assert!(matches!(
+        invalid.rows_into::<Transfer>(),
+        Err(RowError::InvalidValue { column, .. }) if column == "amount"
+    ));
+}